@@ -0,0 +1,526 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::symbols::SymbolMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip-8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xo-chip",
+        }
+    }
+}
+
+// Behaviours that differ between interpreters
+#[derive(Clone, Copy)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55/FX65 leave I pointing past the last register
+    pub load_store_increments_i: bool,
+    // BNNN jumps to NNN plus VX (BXNN) instead of V0
+    pub jump_uses_vx: bool,
+    // Sprites are clipped at the screen edge instead of wrapping
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    pub fn preset(platform: Platform) -> Quirks {
+        match platform {
+            Platform::Chip8 => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+            },
+            Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                clip_sprites: false,
+            },
+        }
+    }
+}
+
+// Static scan of the instructions reachable from the entry point
+pub struct Analysis {
+    rom_size: usize,
+    reachable: BTreeSet<u16>,
+    // Addresses that start a basic block
+    leaders: BTreeSet<u16>,
+    schip_opcodes: Vec<u16>,
+    xochip_opcodes: Vec<u16>,
+    invalid_opcodes: Vec<u16>,
+    // Shifts with X != Y where VY was written earlier in the same block
+    shifts_using_set_vy: Vec<u16>,
+    // Shifts with X != Y where nothing in the block set VY
+    shifts_ignoring_vy: Vec<u16>,
+    // FX55/FX65 followed by a use of I before I is reassigned
+    i_used_after_load_store: Vec<u16>,
+    indirect_jumps: Vec<u16>,
+    // DXYN with known coordinates that cross the right or bottom edge
+    edge_draws: Vec<u16>,
+}
+
+impl Analysis {
+    const START_ADDRESS: u16 = 0x200;
+    const MEMORY_SIZE: usize = 4096;
+
+    pub fn run(rom: &[u8]) -> Analysis {
+        Analysis::run_from(rom, &[Analysis::START_ADDRESS])
+    }
+
+    // Also follows control flow from extra entry points, such as guessed BNNN targets
+    pub fn run_from(rom: &[u8], entries: &[u16]) -> Analysis {
+        let mut memory = [0u8; Analysis::MEMORY_SIZE];
+        let length = rom.len().min(Analysis::MEMORY_SIZE - Analysis::START_ADDRESS as usize);
+        memory[Analysis::START_ADDRESS as usize..Analysis::START_ADDRESS as usize + length]
+            .copy_from_slice(&rom[..length]);
+
+        let mut analysis = Analysis {
+            rom_size: rom.len(),
+            reachable: BTreeSet::new(),
+            leaders: BTreeSet::new(),
+            schip_opcodes: Vec::new(),
+            xochip_opcodes: Vec::new(),
+            invalid_opcodes: Vec::new(),
+            shifts_using_set_vy: Vec::new(),
+            shifts_ignoring_vy: Vec::new(),
+            i_used_after_load_store: Vec::new(),
+            indirect_jumps: Vec::new(),
+            edge_draws: Vec::new(),
+        };
+
+        let leaders = analysis.find_reachable(&memory, entries);
+        for leader in &leaders {
+            analysis.scan_block(&memory, *leader, &leaders);
+        }
+        analysis.leaders = leaders;
+
+        analysis
+    }
+
+    // Walks every control flow path from the entry point and returns the
+    // addresses that start a basic block
+    fn find_reachable(&mut self, memory: &[u8], entries: &[u16]) -> BTreeSet<u16> {
+        let mut leaders = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        for entry in entries {
+            queue.push_back(*entry);
+            leaders.insert(*entry);
+        }
+
+        while let Some(address) = queue.pop_front() {
+            if address as usize + 1 >= memory.len() || self.reachable.contains(&address) {
+                continue;
+            }
+            self.reachable.insert(address);
+
+            let opcode = read_opcode(memory, address);
+            let platform = match classify(opcode) {
+                Some(p) => p,
+                None => {
+                    self.invalid_opcodes.push(address);
+                    continue;
+                }
+            };
+            match platform {
+                Platform::SuperChip => self.schip_opcodes.push(address),
+                Platform::XoChip => self.xochip_opcodes.push(address),
+                Platform::Chip8 => {}
+            }
+
+            let next = address.wrapping_add(instruction_length(opcode));
+            let mut branch = |target: u16, queue: &mut VecDeque<u16>| {
+                leaders.insert(target);
+                queue.push_back(target);
+            };
+
+            match opcode >> 12 {
+                0x0 if opcode == 0x00EE || opcode == 0x00FD => {}
+                0x1 => branch(opcode & 0xFFF, &mut queue),
+                0x2 => {
+                    branch(opcode & 0xFFF, &mut queue);
+                    branch(next, &mut queue);
+                }
+                0xB => self.indirect_jumps.push(address),
+                _ if is_skip(opcode) => {
+                    // A skipped XO-CHIP long load is four bytes
+                    let skipped_length = instruction_length(read_opcode(memory, next));
+                    branch(next, &mut queue);
+                    branch(next.wrapping_add(skipped_length), &mut queue);
+                }
+                _ => queue.push_back(next),
+            }
+        }
+
+        leaders
+    }
+
+    // Tracks register contents through one basic block for the quirk heuristics
+    fn scan_block(&mut self, memory: &[u8], start: u16, leaders: &BTreeSet<u16>) {
+        let mut constants: [Option<u8>; 16] = [None; 16];
+        let mut written = [false; 16];
+        let mut pending_load_store: Option<u16> = None;
+
+        let mut address = start;
+        loop {
+            if !self.reachable.contains(&address) {
+                break;
+            }
+
+            let opcode = read_opcode(memory, address);
+            if classify(opcode).is_none() {
+                break;
+            }
+
+            let x = ((opcode >> 8) & 0xF) as usize;
+            let y = ((opcode >> 4) & 0xF) as usize;
+            let n = (opcode & 0xF) as u8;
+            let nn = (opcode & 0xFF) as u8;
+
+            if let Some(load_store) = pending_load_store {
+                if reads_i(opcode) {
+                    self.i_used_after_load_store.push(load_store);
+                    pending_load_store = None;
+                }
+            }
+            if writes_i(opcode) {
+                pending_load_store = None;
+            }
+
+            match opcode >> 12 {
+                0x6 => constants[x] = Some(nn),
+                0x7 => constants[x] = constants[x].map(|v| v.wrapping_add(nn)),
+                0x8 => {
+                    let vx = constants[x];
+                    let vy = constants[y];
+                    let both = |f: fn(u8, u8) -> u8| match (vx, vy) {
+                        (Some(a), Some(b)) => Some(f(a, b)),
+                        _ => None,
+                    };
+                    let result = match n {
+                        0x0 => vy,
+                        0x1 => both(|a, b| a | b),
+                        0x2 => both(|a, b| a & b),
+                        0x3 => both(|a, b| a ^ b),
+                        _ => None,
+                    };
+
+                    if (n == 0x6 || n == 0xE) && x != y {
+                        if written[y] {
+                            self.shifts_using_set_vy.push(address);
+                        } else {
+                            self.shifts_ignoring_vy.push(address);
+                        }
+                    }
+
+                    constants[x] = result;
+                    if n >= 0x4 {
+                        constants[0xF] = None;
+                        written[0xF] = true;
+                    }
+                }
+                0x5 if n == 0x3 => {
+                    let (low, high) = (x.min(y), x.max(y));
+                    constants[low..=high].fill(None);
+                    written[low..=high].fill(true);
+                }
+                0xC => constants[x] = None,
+                0xD => {
+                    if let (Some(vx), Some(vy)) = (constants[x], constants[y]) {
+                        let (width, height) = if n == 0 { (16, 16) } else { (8, n as usize) };
+                        if (vx as usize % 64) + width > 64 || (vy as usize % 32) + height > 32 {
+                            self.edge_draws.push(address);
+                        }
+                    }
+                    constants[0xF] = None;
+                    written[0xF] = true;
+                }
+                0xF => match nn {
+                    0x07 | 0x0A | 0x85 => constants[x] = None,
+                    0x55 => pending_load_store = Some(address),
+                    0x65 => {
+                        constants[..=x].fill(None);
+                        written[..=x].fill(true);
+                        pending_load_store = Some(address);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+
+            if writes_vx(opcode) {
+                written[x] = true;
+            }
+
+            if ends_block(opcode) {
+                break;
+            }
+            address = address.wrapping_add(instruction_length(opcode));
+            if leaders.contains(&address) {
+                break;
+            }
+        }
+    }
+
+    pub fn is_reachable(&self, address: u16) -> bool {
+        self.reachable.contains(&address)
+    }
+
+    pub fn leaders(&self) -> &BTreeSet<u16> {
+        &self.leaders
+    }
+
+    pub fn indirect_jumps(&self) -> &[u16] {
+        &self.indirect_jumps
+    }
+
+    pub fn platform(&self) -> Platform {
+        if !self.xochip_opcodes.is_empty() {
+            Platform::XoChip
+        } else if !self.schip_opcodes.is_empty() {
+            Platform::SuperChip
+        } else {
+            Platform::Chip8
+        }
+    }
+
+    // Platform preset, switched to SCHIP-style shifts when a plain CHIP-8 ROM
+    // shifts registers without ever setting VY
+    pub fn suggested_preset(&self) -> Platform {
+        let platform = self.platform();
+        if platform == Platform::Chip8
+            && self.shifts_using_set_vy.is_empty()
+            && !self.shifts_ignoring_vy.is_empty() {
+            Platform::SuperChip
+        } else {
+            platform
+        }
+    }
+
+    pub fn report(&self, symbols: &SymbolMap) -> String {
+        let list = |addresses: &[u16]| -> String {
+            if addresses.is_empty() {
+                return "none".to_string();
+            }
+            let mut sorted = addresses.to_vec();
+            sorted.sort();
+            sorted.dedup();
+            let shown: Vec<String> = sorted.iter().take(8).map(|a| symbols.describe(*a)).collect();
+            let more = if sorted.len() > 8 { format!(", ... ({} total)", sorted.len()) } else { String::new() };
+            format!("{}{}", shown.join(", "), more)
+        };
+
+        let reachable_bytes: usize = self.reachable.len() * 2;
+        let mut out = String::new();
+        out.push_str(&format!("ROM size: {} bytes\n", self.rom_size));
+        out.push_str(&format!("Reachable instructions: {} ({} bytes)\n", self.reachable.len(), reachable_bytes));
+        if !self.indirect_jumps.is_empty() {
+            out.push_str("Note: BNNN jump targets are not followed, so code behind them is not analyzed\n");
+        }
+
+        out.push_str("\nOpcodes:\n");
+        out.push_str(&format!("  SCHIP:    {}\n", list(&self.schip_opcodes)));
+        out.push_str(&format!("  XO-CHIP:  {}\n", list(&self.xochip_opcodes)));
+        out.push_str(&format!("  Invalid:  {}\n", list(&self.invalid_opcodes)));
+
+        out.push_str("\nQuirk evidence:\n");
+        out.push_str(&format!("  Shifts reading a VY set earlier:    {}\n", list(&self.shifts_using_set_vy)));
+        out.push_str(&format!("  Shifts with an unset VY:            {}\n", list(&self.shifts_ignoring_vy)));
+        out.push_str(&format!("  I used after FX55/FX65:             {}\n", list(&self.i_used_after_load_store)));
+        out.push_str(&format!("  BNNN jumps:                         {}\n", list(&self.indirect_jumps)));
+        out.push_str(&format!("  Sprite draws crossing an edge:      {}\n", list(&self.edge_draws)));
+
+        let preset = self.suggested_preset();
+        let quirks = Quirks::preset(preset);
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        out.push_str(&format!("\nSuggested preset: {} (detected platform: {})\n", preset.name(), self.platform().name()));
+        out.push_str(&format!("  shift_uses_vy            {}\n", yes_no(quirks.shift_uses_vy)));
+        out.push_str(&format!("  load_store_increments_i  {}{}\n", yes_no(quirks.load_store_increments_i),
+            if self.i_used_after_load_store.is_empty() { "" } else { "  (ROM reads I after FX55/FX65, check this one)" }));
+        out.push_str(&format!("  jump_uses_vx             {}{}\n", yes_no(quirks.jump_uses_vx),
+            if self.indirect_jumps.is_empty() { "  (no BNNN, either works)" } else { "" }));
+        out.push_str(&format!("  clip_sprites             {}{}\n", yes_no(quirks.clip_sprites),
+            if self.edge_draws.is_empty() { "" } else { "  (ROM draws across edges, check this one)" }));
+
+        out
+    }
+}
+
+fn read_opcode(memory: &[u8], address: u16) -> u16 {
+    let a = address as usize % memory.len();
+    ((memory[a] as u16) << 8) | memory[(a + 1) % memory.len()] as u16
+}
+
+// F000 NNNN is the only four byte instruction
+fn instruction_length(opcode: u16) -> u16 {
+    if opcode == 0xF000 { 4 } else { 2 }
+}
+
+// Platform an opcode first appeared on, None when no platform defines it
+pub fn classify(opcode: u16) -> Option<Platform> {
+    let x = (opcode >> 8) & 0xF;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 | 0x00EE => Some(Platform::Chip8),
+            0x00C0..=0x00CF | 0x00FB..=0x00FF => Some(Platform::SuperChip),
+            0x00D0..=0x00DF => Some(Platform::XoChip),
+            // 0NNN machine code routines
+            _ => Some(Platform::Chip8),
+        },
+        0x5 => match n {
+            0x0 => Some(Platform::Chip8),
+            0x2 | 0x3 => Some(Platform::XoChip),
+            _ => None,
+        },
+        0x8 => match n {
+            0x0..=0x7 | 0xE => Some(Platform::Chip8),
+            _ => None,
+        },
+        0x9 if n != 0 => None,
+        0xD if n == 0 => Some(Platform::SuperChip),
+        0xE => match nn {
+            0x9E | 0xA1 => Some(Platform::Chip8),
+            _ => None,
+        },
+        0xF => match nn {
+            0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55 | 0x65 => Some(Platform::Chip8),
+            0x30 | 0x75 | 0x85 => Some(Platform::SuperChip),
+            0x00 | 0x02 if x == 0 => Some(Platform::XoChip),
+            0x01 | 0x3A => Some(Platform::XoChip),
+            _ => None,
+        },
+        _ => Some(Platform::Chip8),
+    }
+}
+
+fn is_skip(opcode: u16) -> bool {
+    match opcode >> 12 {
+        0x3 | 0x4 => true,
+        0x5 | 0x9 => opcode & 0xF == 0,
+        0xE => matches!(opcode & 0xFF, 0x9E | 0xA1),
+        _ => false,
+    }
+}
+
+fn ends_block(opcode: u16) -> bool {
+    matches!(opcode >> 12, 0x1 | 0x2 | 0xB) || opcode == 0x00EE || opcode == 0x00FD || is_skip(opcode)
+}
+
+fn reads_i(opcode: u16) -> bool {
+    match opcode >> 12 {
+        0xD => true,
+        0x5 => matches!(opcode & 0xF, 0x2 | 0x3),
+        0xF => matches!(opcode & 0xFF, 0x1E | 0x33 | 0x55 | 0x65),
+        _ => false,
+    }
+}
+
+fn writes_i(opcode: u16) -> bool {
+    match opcode >> 12 {
+        0xA => true,
+        0xF => matches!(opcode & 0xFF, 0x29 | 0x30) || opcode == 0xF000,
+        _ => false,
+    }
+}
+
+fn writes_vx(opcode: u16) -> bool {
+    match opcode >> 12 {
+        0x6 | 0x7 | 0x8 | 0xC => true,
+        0xF => matches!(opcode & 0xFF, 0x07 | 0x0A | 0x85),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(opcodes: &[u16]) -> Vec<u8> {
+        opcodes.iter().flat_map(|o| o.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn classifies_opcodes_by_platform() {
+        for opcode in [0x00E0, 0x00EE, 0x0123, 0x1200, 0x5120, 0x812E, 0xD125, 0xE19E, 0xF165] {
+            assert_eq!(classify(opcode), Some(Platform::Chip8), "{:04X}", opcode);
+        }
+        for opcode in [0x00C2, 0x00FB, 0x00FF, 0xD120, 0xF130, 0xF175, 0xF185] {
+            assert_eq!(classify(opcode), Some(Platform::SuperChip), "{:04X}", opcode);
+        }
+        for opcode in [0x00D2, 0x5122, 0x5123, 0xF000, 0xF002, 0xF201, 0xF13A] {
+            assert_eq!(classify(opcode), Some(Platform::XoChip), "{:04X}", opcode);
+        }
+        for opcode in [0x5121, 0x8128, 0x9121, 0xE1A2, 0xF1FF, 0xF100] {
+            assert_eq!(classify(opcode), None, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn follows_jumps_calls_and_skips() {
+        // 200: CALL 208; 202: SE V0, 0; 204: JP 20C; 206: data; 208: RET; 20A: data; 20C: JP 20C
+        let analysis = Analysis::run(&rom(&[0x2208, 0x3000, 0x120C, 0xFFFF, 0x00EE, 0xFFFF, 0x120C]));
+        for address in [0x200, 0x202, 0x204, 0x206, 0x208, 0x20C] {
+            assert!(analysis.is_reachable(address), "{:03X}", address);
+        }
+        assert!(!analysis.is_reachable(0x20A));
+        let leaders: Vec<u16> = analysis.leaders().iter().copied().collect();
+        assert_eq!(leaders, vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20C]);
+        // The skipped data word is reached by the skip, so it counts as invalid
+        assert_eq!(analysis.invalid_opcodes, vec![0x206]);
+        assert_eq!(analysis.platform(), Platform::Chip8);
+    }
+
+    #[test]
+    fn detects_the_platform_from_reachable_code_only() {
+        // SCHIP scroll behind an infinite loop is never reached
+        let analysis = Analysis::run(&rom(&[0x1200, 0x00FB]));
+        assert_eq!(analysis.platform(), Platform::Chip8);
+
+        let analysis = Analysis::run(&rom(&[0x00FF, 0x1202]));
+        assert_eq!(analysis.platform(), Platform::SuperChip);
+
+        // XO-CHIP wins over SCHIP
+        let analysis = Analysis::run(&rom(&[0x00FF, 0xF201, 0x1204]));
+        assert_eq!(analysis.platform(), Platform::XoChip);
+    }
+
+    #[test]
+    fn suggests_schip_shifts_when_vy_is_never_set() {
+        // SHR V1, V2 without setting V2 first
+        let analysis = Analysis::run(&rom(&[0x8126, 0x1202]));
+        assert_eq!(analysis.suggested_preset(), Platform::SuperChip);
+
+        // LD V2, 1; SHR V1, V2
+        let analysis = Analysis::run(&rom(&[0x6201, 0x8126, 0x1204]));
+        assert_eq!(analysis.suggested_preset(), Platform::Chip8);
+    }
+
+    #[test]
+    fn finds_quirk_evidence() {
+        // LD [I], V3; ADD I, V0; LD V0, 60; LD V1, 30; DRW V0, V1, 5; JP V0, 200
+        let analysis = Analysis::run(&rom(&[0xF355, 0xF01E, 0x603C, 0x611E, 0xD015, 0xB200]));
+        assert_eq!(analysis.i_used_after_load_store, vec![0x200]);
+        assert_eq!(analysis.edge_draws, vec![0x208]);
+        assert_eq!(analysis.indirect_jumps(), &[0x20A]);
+    }
+}
@@ -0,0 +1,292 @@
+extern crate sdl2;
+
+use std::f32::consts::PI;
+
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+#[derive(Clone)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub const NAMES: [&'static str; 3] = ["square", "sine", "triangle"];
+
+    pub fn named(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+
+    // Level from -1 to 1 at phase 0 to 1 through a period
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+// The beeper's sound, generated one 60 Hz frame at a time so it stays in step
+// with emulation. The tone fades in and out over a few milliseconds instead of
+// switching at full volume, which would click.
+#[derive(Clone)]
+pub struct Tone {
+    sample_rate: u32,
+    frequency: f32,
+    waveform: Waveform,
+    volume: f32,
+
+    // Position in the current period, 0 to 1
+    phase: f32,
+    // Envelope, 0 to 1
+    gain: f32,
+    // Frames run so far, to spread sample_rate / 60 samples evenly over them
+    frames: u64,
+}
+
+impl Tone {
+    const FRAME_RATE: u64 = 60;
+    const FADE_SECONDS: f32 = 0.005;
+
+    pub fn new(sample_rate: u32, frequency: f32, waveform: Waveform, volume: f32) -> Tone {
+        Tone {
+            sample_rate,
+            frequency,
+            waveform,
+            volume: volume.clamp(0.0, 1.0),
+            phase: 0.0,
+            gain: 0.0,
+            frames: 0,
+        }
+    }
+
+    // Samples for the next frame, with the tone sounding if on
+    pub fn frame(&mut self, on: bool) -> Vec<f32> {
+        // Frame n ends at sample n * sample_rate / 60, so no rounding error builds up
+        let rate = self.sample_rate as u64;
+        let count = (self.frames + 1) * rate / Tone::FRAME_RATE - self.frames * rate / Tone::FRAME_RATE;
+        self.frames += 1;
+
+        let target = if on { 1.0 } else { 0.0 };
+        (0..count).map(|_| self.next_sample(target)).collect()
+    }
+
+    // Samples carrying on from the last frame and fading to silence, for when
+    // no frame follows in time. The tone itself is left as it is.
+    pub fn tail(&self) -> Vec<f32> {
+        let mut tone = self.clone();
+        let mut samples = Vec::new();
+        while tone.gain > 0.0 {
+            samples.push(tone.next_sample(0.0));
+        }
+        samples
+    }
+
+    fn fade_step(&self) -> f32 {
+        1.0 / (Tone::FADE_SECONDS * self.sample_rate as f32)
+    }
+
+    // Moves the envelope one step towards target and the phase one sample on
+    fn next_sample(&mut self, target: f32) -> f32 {
+        let fade_step = self.fade_step();
+        self.gain = if self.gain < target {
+            (self.gain + fade_step).min(target)
+        } else {
+            (self.gain - fade_step).max(target)
+        };
+        let sample = self.waveform.sample(self.phase) * self.gain * self.volume;
+        self.phase = (self.phase + self.frequency / self.sample_rate as f32).fract();
+        sample
+    }
+}
+
+// The tone, played on the default audio device once play succeeds and
+// otherwise only generated, e.g. for a WAV file. Muting only silences playback.
+pub struct Beeper {
+    tone: Tone,
+    queue: Option<AudioQueue<f32>>,
+    muted: bool,
+    // Playback volume from 0 to 1, fading to 0 while muted and to 1 otherwise.
+    // Also drops to 0 when the queue is left to run dry, so frames that resume
+    // fade in rather than starting mid-wave.
+    level: f32,
+}
+
+impl Beeper {
+    const SAMPLE_RATE: u32 = 48000;
+    // Frames of audio allowed to wait in the queue. When fast forwarding, frames
+    // beyond this are dropped rather than played late.
+    const MAX_QUEUED_FRAMES: u32 = 4;
+
+    pub fn new(frequency: f32, waveform: Waveform, volume: f32) -> Beeper {
+        Beeper {
+            tone: Tone::new(Beeper::SAMPLE_RATE, frequency, waveform, volume),
+            queue: None,
+            muted: false,
+            level: 0.0,
+        }
+    }
+
+    // Fails when there's no audio device, e.g. SDL_AUDIODRIVER names one that isn't available.
+    // The device may change the sample rate, so call this before get_sample_rate.
+    pub fn play(&mut self, context: &sdl2::Sdl) -> Result<(), String> {
+        let audio = context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(Beeper::SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: Some(512),
+        };
+        let queue = audio.open_queue::<f32, _>(None, &desired)?;
+        self.tone.sample_rate = queue.spec().freq as u32;
+        queue.resume();
+        self.queue = Some(queue);
+        Ok(())
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.tone.sample_rate
+    }
+
+    // Returns whether the beeper is now muted
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    // Generates the next frame, queues it for playing and returns it, unmuted
+    pub fn frame(&mut self, on: bool) -> Vec<f32> {
+        let samples = self.tone.frame(on);
+        let limit = Beeper::MAX_QUEUED_FRAMES * self.frame_bytes();
+        if self.queue.as_ref().is_some_and(|q| q.size() < limit) {
+            let played = self.playback(&samples);
+            if let Some(queue) = &self.queue {
+                // Nothing to do about a failure but stay silent
+                let _ = queue.queue_audio(&played);
+            }
+        }
+        samples
+    }
+
+    // Samples as they are played, faded by the playback level
+    fn playback(&mut self, samples: &[f32]) -> Vec<f32> {
+        let target = if self.muted { 0.0 } else { 1.0 };
+        let fade_step = self.tone.fade_step();
+        let level = &mut self.level;
+        samples.iter().map(|s| {
+            *level = if *level < target { (*level + fade_step).min(target) } else { (*level - fade_step).max(target) };
+            s * *level
+        }).collect()
+    }
+
+    // Call when a loop iteration runs no frame, e.g. while paused. Once the
+    // queue is about to run dry, queues the tone fading out so playback stops
+    // at silence rather than cutting off mid-wave, which clicks.
+    pub fn idle(&mut self) {
+        if let Some(queue) = &self.queue {
+            if self.level > 0.0 && queue.size() < 2 * self.frame_bytes() {
+                let tail: Vec<f32> = self.tone.tail().iter().map(|s| s * self.level).collect();
+                let _ = queue.queue_audio(&tail);
+                self.level = 0.0;
+            }
+        }
+    }
+
+    // Size of one frame of samples in the queue
+    fn frame_bytes(&self) -> u32 {
+        self.tone.sample_rate / Tone::FRAME_RATE as u32 * std::mem::size_of::<f32>() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_samples_evenly_over_frames() {
+        for rate in [48000, 44100, 22050, 8000] {
+            let mut tone = Tone::new(rate, 440.0, Waveform::Square, 0.5);
+            let counts: Vec<usize> = (0..Tone::FRAME_RATE).map(|_| tone.frame(false).len()).collect();
+            assert_eq!(counts.iter().sum::<usize>(), rate as usize, "{}", rate);
+            let low = rate as usize / Tone::FRAME_RATE as usize;
+            assert!(counts.iter().all(|&c| c == low || c == low + 1), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn fades_in_and_out() {
+        let mut tone = Tone::new(48000, 440.0, Waveform::Square, 0.5);
+        // 5 ms at 48 kHz
+        let fade = 240;
+
+        let on = tone.frame(true);
+        assert!(on[0] > 0.0 && on[0] < 0.01);
+        assert!(on[..fade - 1].iter().all(|s| s.abs() < 0.5));
+        assert!(on[fade..].iter().all(|s| s.abs() == 0.5));
+
+        let off = tone.frame(false);
+        assert!(off[0].abs() > 0.49);
+        assert!(off[fade..].iter().all(|&s| s == 0.0));
+        assert!(tone.frame(false).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn mutes_playback_only() {
+        let mut beeper = Beeper::new(440.0, Waveform::Sine, 1.0);
+        assert!(beeper.toggle_mute());
+        let samples = beeper.frame(true);
+        assert!(samples.iter().any(|&s| s != 0.0));
+        assert!(beeper.playback(&samples).iter().all(|&s| s == 0.0));
+
+        // Fades back in over 5 ms once unmuted
+        assert!(!beeper.toggle_mute());
+        let samples = beeper.frame(true);
+        let played = beeper.playback(&samples);
+        assert!(played[0].abs() < 0.01);
+        assert!(played[240..].iter().any(|&s| s.abs() > 0.99));
+    }
+
+    #[test]
+    fn tail_fades_out_without_changing_the_tone() {
+        let mut tone = Tone::new(48000, 440.0, Waveform::Triangle, 1.0);
+        tone.frame(true);
+        let mut continued = tone.clone();
+
+        let tail = tone.tail();
+        assert!((239..=241).contains(&tail.len()));
+        assert_eq!(*tail.last().unwrap(), 0.0);
+        assert_eq!(tone.frame(true), continued.frame(true));
+
+        assert!(Tone::new(48000, 440.0, Waveform::Sine, 1.0).tail().is_empty());
+    }
+
+    #[test]
+    fn plays_on_the_dummy_audio_driver() {
+        std::env::set_var("SDL_AUDIODRIVER", "dummy");
+        let context = sdl2::init().unwrap();
+        let mut beeper = Beeper::new(440.0, Waveform::Square, 0.25);
+        beeper.play(&context).unwrap();
+
+        let rate = beeper.get_sample_rate() as usize;
+        for _ in 0..10 {
+            let samples = beeper.frame(true);
+            assert!(samples.len() == rate / 60 || samples.len() == rate / 60 + 1);
+        }
+        let queued = beeper.queue.as_ref().unwrap().size();
+        assert!(queued > 0 && queued <= (Beeper::MAX_QUEUED_FRAMES + 1) * beeper.frame_bytes());
+
+        // The tail goes out once, then playback fades back in
+        beeper.queue.as_ref().unwrap().clear();
+        beeper.idle();
+        assert_eq!(beeper.level, 0.0);
+        assert!(beeper.queue.as_ref().unwrap().size() > 0);
+        beeper.frame(true);
+        assert!(beeper.level > 0.0);
+    }
+}
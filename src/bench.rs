@@ -0,0 +1,220 @@
+use std::time::{Duration, Instant};
+
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+
+// Representative programs for the built-in suite. Each one loops forever.
+pub const SUITE: [(&str, &[u8]); 3] = [
+    // Font digits drawn across the screen, cleared every 256 sprites
+    ("draw", &[
+        0x63, 0x0F, // 200 LD V3, 0x0F
+        0x60, 0x00, // 202 LD V0, 0
+        0x61, 0x00, // 204 LD V1, 0
+        0x62, 0x00, // 206 LD V2, 0
+        0x00, 0xE0, // 208 CLS
+        0xF2, 0x29, // 20A LD F, V2
+        0xD0, 0x15, // 20C DRW V0, V1, 5
+        0x70, 0x05, // 20E ADD V0, 5
+        0x71, 0x03, // 210 ADD V1, 3
+        0x72, 0x01, // 212 ADD V2, 1
+        0x82, 0x32, // 214 AND V2, V3
+        0x74, 0x01, // 216 ADD V4, 1
+        0x34, 0x00, // 218 SE V4, 0
+        0x12, 0x0A, // 21A JP 20A
+        0x12, 0x08, // 21C JP 208
+    ]),
+    // ALU operations and BCD stores
+    ("arithmetic", &[
+        0x60, 0x01, // 200 LD V0, 1
+        0x61, 0x03, // 202 LD V1, 3
+        0x80, 0x14, // 204 ADD V0, V1
+        0x81, 0x05, // 206 SUB V1, V0
+        0x82, 0x06, // 208 SHR V2
+        0x82, 0x13, // 20A XOR V2, V1
+        0x83, 0x0E, // 20C SHL V3
+        0x83, 0x01, // 20E OR V3, V0
+        0x84, 0x12, // 210 AND V4, V1
+        0x84, 0x17, // 212 SUBN V4, V1
+        0x75, 0x37, // 214 ADD V5, 0x37
+        0x85, 0x54, // 216 ADD V5, V5
+        0xA3, 0x00, // 218 LD I, 300
+        0xF5, 0x33, // 21A LD B, V5
+        0x12, 0x04, // 21C JP 204
+    ]),
+    // Three levels of nested subroutines
+    ("call", &[
+        0x22, 0x06, // 200 CALL 206
+        0x70, 0x01, // 202 ADD V0, 1
+        0x12, 0x00, // 204 JP 200
+        0x22, 0x0C, // 206 CALL 20C
+        0x71, 0x01, // 208 ADD V1, 1
+        0x00, 0xEE, // 20A RET
+        0x22, 0x12, // 20C CALL 212
+        0x72, 0x01, // 20E ADD V2, 1
+        0x00, 0xEE, // 210 RET
+        0x73, 0x01, // 212 ADD V3, 1
+        0x00, 0xEE, // 214 RET
+    ]),
+];
+
+// Snapshots per save state run for every instruction of the others. Each one
+// copies the whole machine, which takes far longer than an instruction.
+const SAVE_STATE_DIVISOR: u64 = 100;
+
+// Runs every suite program, then the decoder and save states on their own
+pub fn suite(instructions: u64) -> String {
+    let mut out = String::new();
+    for (name, rom) in SUITE.iter() {
+        out.push_str(&format!("== {} ==\n", name));
+        out.push_str(&run(&rom.to_vec(), instructions));
+    }
+
+    out.push_str("== decoder ==\n");
+    out.push_str(&format_result("decode", instructions, "instructions", measure_decoder(instructions)));
+
+    let snapshots = (instructions / SAVE_STATE_DIVISOR).max(1);
+    out.push_str("== save state ==\n");
+    out.push_str(&format_result("save+restore", snapshots, "snapshots", measure_save_state(&SUITE[0].1.to_vec(), snapshots)));
+    out
+}
+
+// Headless throughput of the interpreter, with and without the decode cache
+pub fn run(rom: &Vec<u8>, instructions: u64) -> String {
+    let interpreted = measure(rom, instructions, false);
+    let cached = measure(rom, instructions, true);
+
+    let mut out = format!("{} instructions per run\n", instructions);
+    out.push_str(&format_result("interpreter", instructions, "instructions", interpreted));
+    out.push_str(&format_result("decode cache", instructions, "instructions", cached));
+    out.push_str(&format!("speedup: {:.2}x\n", interpreted.as_secs_f64() / cached.as_secs_f64()));
+
+    #[cfg(feature = "jit")]
+    {
+        let translated = measure_jit(rom, instructions);
+        out.push_str(&format_result("jit", instructions, "instructions", translated));
+        out.push_str(&format!("speedup: {:.2}x\n", interpreted.as_secs_f64() / translated.as_secs_f64()));
+    }
+
+    out
+}
+
+// Same random numbers for every run so they execute the same path
+fn prepare(rom: &Vec<u8>, decode_cache: bool) -> Chip8 {
+    let mut chip = Chip8::new(rom);
+    chip.seed_rng(0);
+    if decode_cache {
+        chip.enable_decode_cache();
+    }
+    chip
+}
+
+fn measure(rom: &Vec<u8>, instructions: u64, decode_cache: bool) -> Duration {
+    let mut chip = prepare(rom, decode_cache);
+
+    let start = Instant::now();
+    for _ in 0..instructions {
+        chip.cycle();
+    }
+    start.elapsed()
+}
+
+// Instruction::decode over every opcode in turn
+fn measure_decoder(instructions: u64) -> Duration {
+    let start = Instant::now();
+    for i in 0..instructions {
+        std::hint::black_box(Instruction::decode(std::hint::black_box(i as u16)));
+    }
+    start.elapsed()
+}
+
+// Saves the whole machine and restores it again, as save states and rewind
+// would, between instructions of a running program
+fn measure_save_state(rom: &Vec<u8>, snapshots: u64) -> Duration {
+    let mut chip = prepare(rom, false);
+    let mut snapshot = chip.clone();
+
+    let start = Instant::now();
+    for _ in 0..snapshots {
+        chip.cycle();
+        snapshot.clone_from(&chip);
+        chip.clone_from(std::hint::black_box(&snapshot));
+    }
+    start.elapsed()
+}
+
+#[cfg(feature = "jit")]
+fn measure_jit(rom: &Vec<u8>, instructions: u64) -> Duration {
+    let mut chip = Chip8::new(rom);
+    chip.seed_rng(0);
+    let mut jit = crate::jit::Jit::new();
+
+    let start = Instant::now();
+    let mut executed = 0;
+    while executed < instructions {
+        executed += jit.step(&mut chip, instructions - executed);
+    }
+    start.elapsed()
+}
+
+fn format_result(name: &str, count: u64, unit: &str, elapsed: Duration) -> String {
+    format!(
+        "{:<14}{:>10.3} s  {:>14.0} {}/s\n",
+        name,
+        elapsed.as_secs_f64(),
+        count as f64 / elapsed.as_secs_f64(),
+        unit
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_cache_runs_match_the_interpreter() {
+        for (name, rom) in SUITE.iter() {
+            let mut interpreted = prepare(&rom.to_vec(), false);
+            let mut cached = prepare(&rom.to_vec(), true);
+            for _ in 0..100_000 {
+                interpreted.cycle();
+                cached.cycle();
+            }
+
+            assert_eq!(interpreted.get_pc(), cached.get_pc(), "{}", name);
+            assert_eq!(interpreted.get_index_register(), cached.get_index_register(), "{}", name);
+            assert_eq!(interpreted.get_registers(), cached.get_registers(), "{}", name);
+            for address in 0..Chip8::MEMORY_SIZE as u16 {
+                assert_eq!(interpreted.get_memory(address), cached.get_memory(address), "{} {:03X}", name, address);
+            }
+            for y in 0..Chip8::VIDEO_HEIGHT {
+                assert_eq!(interpreted.get_video_row(y), cached.get_video_row(y), "{} row {}", name, y);
+            }
+        }
+    }
+
+    #[test]
+    fn restoring_a_snapshot_replays_the_same_run() {
+        // The draw program covers memory, registers, the stack and the framebuffer
+        let mut chip = prepare(&SUITE[0].1.to_vec(), false);
+        for _ in 0..500 {
+            chip.cycle();
+        }
+        let snapshot = chip.clone();
+        for _ in 0..1000 {
+            chip.cycle();
+        }
+        let first = chip.clone();
+
+        chip.clone_from(&snapshot);
+        assert_eq!(chip.get_pc(), snapshot.get_pc());
+        for _ in 0..1000 {
+            chip.cycle();
+        }
+        assert_eq!(chip.get_pc(), first.get_pc());
+        assert_eq!(chip.get_index_register(), first.get_index_register());
+        assert_eq!(chip.get_registers(), first.get_registers());
+        for y in 0..Chip8::VIDEO_HEIGHT {
+            assert_eq!(chip.get_video_row(y), first.get_video_row(y));
+        }
+    }
+}
@@ -1,11 +1,47 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
+use crate::instruction::Instruction;
+
+#[derive(Clone, Copy)]
+pub enum SmcKind {
+    // A store wrote to an address that had already been executed
+    WriteToExecuted,
+    // An instruction was fetched from an address written by a store
+    ExecuteWritten,
+}
+
+// Self-modifying code event
+#[derive(Clone, Copy)]
+pub struct SmcEvent {
+    pub kind: SmcKind,
+    pub address: u16,
+    // Instruction responsible for the event
+    pub pc: u16,
+    pub cycle: u64,
+}
+
+#[derive(Clone)]
+struct SmcTracker {
+    executed: [bool; 4096],
+    written: [bool; 4096],
+    events: Vec<SmcEvent>,
+}
+
+// Cloning snapshots the whole machine, e.g. for a save state
+#[derive(Clone)]
 pub struct Chip8 {
     registers: [u8; 16],
     memory: [u8; 4096],
     stack: [u16; 16],
     keys: [bool; 16],
-    video: [u32; 64 * 32],
+    // Cycle count when each key was last read by EX9E, EXA1 or FX0A
+    key_polls: [u64; 16],
+    // One packed row per scanline, the leftmost pixel in the most significant bit
+    video: [u64; Chip8::VIDEO_HEIGHT],
+    // Set by instructions that touch the framebuffer, cleared by take_video_changed
+    video_changed: bool,
+    // Memory bytes op_DXYN has read sprite rows from
+    sprite_reads: [bool; 4096],
 
     // Indices
     index_register: u16,
@@ -14,7 +50,12 @@ pub struct Chip8 {
     delay_timer: u8,
     sound_timer: u8,
 
-    rng: rand::rngs::ThreadRng,
+    rng: rand::rngs::StdRng,
+
+    cycles: u64,
+    smc: Option<Box<SmcTracker>>,
+    // Decoded instructions keyed by address, invalidated by stores
+    decode_cache: Option<Box<[Option<Instruction>; 4096]>>,
 }
 
 #[allow(non_snake_case)]
@@ -40,9 +81,9 @@ impl Chip8 {
         0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
         0xF0, 0x80, 0xF0, 0x80, 0x80  // F
     ];
+    pub const MEMORY_SIZE: usize = 4096;
     pub const VIDEO_WIDTH: usize = 64;
     pub const VIDEO_HEIGHT: usize = 32;
-    const VIDEO_SIZE: usize = Chip8::VIDEO_WIDTH * Chip8::VIDEO_HEIGHT;
 
     pub fn new(program: &Vec<u8>) -> Chip8 {
         let mut chip = Chip8 {
@@ -50,7 +91,10 @@ impl Chip8 {
             memory: [0; 4096],
             stack: [0; 16],
             keys: [false; 16],
-            video: [0; Chip8::VIDEO_SIZE],
+            key_polls: [0; 16],
+            video: [0; Chip8::VIDEO_HEIGHT],
+            video_changed: true,
+            sprite_reads: [false; 4096],
 
             index_register: 0,
             pc: Chip8::CODE_START_ADDRESS as u16,
@@ -58,7 +102,11 @@ impl Chip8 {
             delay_timer: 0,
             sound_timer: 0,
 
-            rng: rand::thread_rng(),
+            rng: rand::rngs::StdRng::from_entropy(),
+
+            cycles: 0,
+            smc: None,
+            decode_cache: None,
         };
 
         // Load fonts
@@ -78,93 +126,176 @@ impl Chip8 {
         self.keys[key as usize] = pressed;
     }
 
+    pub fn get_keys(&self) -> &[bool; 16] {
+        &self.keys
+    }
+
+    // Changes whenever the program reads a key, for frontends that show which keys it wants
+    pub fn get_key_polls(&self) -> &[u64; 16] {
+        &self.key_polls
+    }
+
+    // Per-pixel access for frontends that don't walk packed rows
     pub fn get_video(&self, x: usize, y: usize) -> u32 {
-        self.video[y * Chip8::VIDEO_WIDTH + x]
+        ((self.video[y] >> (Chip8::VIDEO_WIDTH - 1 - x)) & 1) as u32
+    }
+
+    // Whole row y, bit 63 is x = 0
+    pub fn get_video_row(&self, y: usize) -> u64 {
+        self.video[y]
+    }
+
+    // Whether the framebuffer changed since the last call
+    pub fn take_video_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.video_changed, false)
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Used by modules generated with `recompile`
+    #[allow(dead_code)]
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn get_index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn get_registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    pub fn get_memory(&self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()]
+    }
+
+    // Makes CXNN results reproducible
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = rand::rngs::StdRng::seed_from_u64(seed);
+    }
+
+    // Instructions executed since reset
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Registers, I and PC for translated code, which runs outside cycle
+    #[cfg(feature = "jit")]
+    pub fn jit_state(&mut self) -> (&mut [u8; 16], &mut u16, &mut u16) {
+        (&mut self.registers, &mut self.index_register, &mut self.pc)
+    }
+
+    // Accounts for count instructions run by translated code. Used by the JIT
+    // and by modules generated with `recompile`.
+    #[allow(dead_code)]
+    pub fn retire(&mut self, count: u64) {
+        self.cycles += count;
+    }
+
+    // Cache decoded instructions instead of decoding every fetch
+    pub fn enable_decode_cache(&mut self) {
+        if self.decode_cache.is_none() {
+            self.decode_cache = Some(Box::new([None; 4096]));
+        }
+    }
+
+    // Start recording writes into executed code and execution of written memory
+    pub fn enable_smc_detection(&mut self) {
+        if self.smc.is_none() {
+            self.smc = Some(Box::new(SmcTracker {
+                executed: [false; 4096],
+                written: [false; 4096],
+                events: Vec::new(),
+            }));
+        }
+    }
+
+    // Whether a store has written to address since it was last executed.
+    // Always false while detection is disabled.
+    pub fn was_written(&self, address: u16) -> bool {
+        match self.smc.as_ref() {
+            Some(smc) => smc.written[address as usize % 4096],
+            None => false,
+        }
+    }
+
+    // Self-modifying code events recorded since the last call
+    pub fn take_smc_events(&mut self) -> Vec<SmcEvent> {
+        match self.smc.as_mut() {
+            Some(smc) => std::mem::take(&mut smc.events),
+            None => Vec::new(),
+        }
+    }
+
+    // Whether op_DXYN has drawn a sprite row from this address
+    pub fn was_drawn_from(&self, address: u16) -> bool {
+        self.sprite_reads[address as usize % self.sprite_reads.len()]
+    }
+
+    // Big-endian instruction word stored at address
+    pub fn get_opcode(&self, address: u16) -> u16 {
+        ((self.get_memory(address) as u16) << 8) | self.get_memory(address.wrapping_add(1)) as u16
     }
 
     // 1 cpu cycle
     pub fn cycle(&mut self) {
         //println!("{:?}", self.video);
 
-        let n0 = self.memory[self.pc as usize] >> 4;
-        let n1 = self.memory[self.pc as usize] & 0b00001111;
-        let n2 = self.memory[(self.pc + 1) as usize] >> 4;
-        let n3 = self.memory[(self.pc + 1) as usize] & 0b00001111;
-        self.pc += 2;
-
-        let nnn = ((n1 as u16) << 8) | ((n2 as u16) << 4) | (n3 as u16);
-        let nn = (n2 << 4) | n3;
-
-        // execute
-        match n0 {
-            0 => {
-                match n3 {
-                    0 => self.op_00E0(),
-                    0xE => self.op_00EE(),
-                    _ => panic!("Unhandled opcode! {}{}{}{}", n0, n1, n2, n3)
-                }
-            }
-            1 => self.op_1NNN(nnn),
-            2 => self.op_2NNN(nnn),
-            3 => self.op_3XNN(n1 as usize, nn),
-            4 => self.op_4XNN(n1 as usize, nn),
-            5 => self.op_5XY0(n1 as usize, n2 as usize),
-            6 => self.op_6XNN(n1 as usize, nn),
-            7 => self.op_7XNN(n1 as usize, nn),
-            8 => {
-                match n3 {
-                    0 => self.op_8XY0(n1 as usize, n2 as usize),
-                    1 => self.op_8XY1(n1 as usize, n2 as usize),
-                    2 => self.op_8XY2(n1 as usize, n2 as usize),
-                    3 => self.op_8XY3(n1 as usize, n2 as usize),
-                    4 => self.op_8XY4(n1 as usize, n2 as usize),
-                    5 => self.op_8XY5(n1 as usize, n2 as usize),
-                    6 => self.op_8XY6(n1 as usize),
-                    7 => self.op_8XY7(n1 as usize, n2 as usize),
-                    0xE => self.op_8XYE(n1 as usize),
-                    _ => panic!("Unhandled opcode! {}{}{}{}", n0, n1, n2, n3)
-                }
-            }
-            9 => self.op_9XY0(n1 as usize, n2 as usize),
-            0xA => self.op_ANNN(nnn),
-            0xB => self.op_BNNN(nnn),
-            0xC => self.op_CXNN(n1 as usize, nn),
-            0xD => self.op_DXYN(n1 as usize, n2 as usize, n3),
-            0xE => {
-                if n2 == 0xA && n3 == 0x1 {
-                    self.op_EXA1(n1 as usize);
-                } else if n2 == 0x9 && n3 == 0xE {
-                    self.op_EX9E(n1 as usize);
-                } else {
-                    panic!("Unhandled opcode! {}{}{}{}", n0, n1, n2, n3);
+        if let Some(smc) = self.smc.as_mut() {
+            for address in [self.pc as usize, (self.pc as usize + 1) % 4096] {
+                if smc.written[address] {
+                    // Report each write once, when its result is first executed
+                    smc.written[address] = false;
+                    smc.events.push(SmcEvent {
+                        kind: SmcKind::ExecuteWritten,
+                        address: address as u16,
+                        pc: self.pc,
+                        cycle: self.cycles,
+                    });
                 }
+                smc.executed[address] = true;
             }
-            0xF => {
-                if n2 == 0x0 && n3 == 0x7 {
-                    self.op_FX07(n1 as usize);
-                } else if n2 == 0x0 && n3 == 0xA {
-                    self.op_FX0A(n1 as usize);
-                } else if n2 == 0x1 && n3 == 0x5 {
-                    self.op_FX15(n1 as usize);
-                } else if n2 == 0x1 && n3 == 0x8 {
-                    self.op_FX18(n1 as usize);
-                } else if n2 == 0x1 && n3 == 0xE {
-                    self.op_FX1E(n1 as usize);
-                } else if n2 == 0x2 && n3 == 0x9 {
-                    self.op_FX29(n1 as usize);
-                } else if n2 == 0x3 && n3 == 0x3 {
-                    self.op_FX33(n1 as usize);
-                } else if n2 == 0x5 && n3 == 0x5 {
-                    self.op_FX55(n1 as usize);
-                } else if n2 == 0x6 && n3 == 0x5 {
-                    self.op_FX65(n1 as usize);
-                } else {
-                    panic!("Unhandled opcode! {}{}{}{}", n0, n1, n2, n3);
-                }
-            }
-            _ => panic!("Unhandled opcode! {}{}{}{}", n0, n1, n2, n3),
         }
+        self.cycles += 1;
+
+        let address = self.pc as usize;
+        self.pc += 2;
+
+        let memory = &self.memory;
+        let fetch = || ((memory[address] as u16) << 8) | memory[address + 1] as u16;
+        let instruction = match self.decode_cache.as_mut() {
+            Some(cache) => match cache[address] {
+                Some(instruction) => instruction,
+                None => {
+                    let instruction = Instruction::decode(fetch());
+                    cache[address] = Some(instruction);
+                    instruction
+                }
+            },
+            None => Instruction::decode(fetch()),
+        };
 
+        self.execute(instruction);
+    }
+
+    // Counts the delay and sound timers down, called at 60 Hz independently of cycle
+    pub fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -175,14 +306,53 @@ impl Chip8 {
     }
 
 
+    fn execute(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Op00E0 => self.op_00E0(),
+            Instruction::Op00EE => self.op_00EE(),
+            Instruction::Op1NNN(nnn) => self.op_1NNN(nnn),
+            Instruction::Op2NNN(nnn) => self.op_2NNN(nnn),
+            Instruction::Op3XNN(x, nn) => self.op_3XNN(x as usize, nn),
+            Instruction::Op4XNN(x, nn) => self.op_4XNN(x as usize, nn),
+            Instruction::Op5XY0(x, y) => self.op_5XY0(x as usize, y as usize),
+            Instruction::Op6XNN(x, nn) => self.op_6XNN(x as usize, nn),
+            Instruction::Op7XNN(x, nn) => self.op_7XNN(x as usize, nn),
+            Instruction::Op8XY0(x, y) => self.op_8XY0(x as usize, y as usize),
+            Instruction::Op8XY1(x, y) => self.op_8XY1(x as usize, y as usize),
+            Instruction::Op8XY2(x, y) => self.op_8XY2(x as usize, y as usize),
+            Instruction::Op8XY3(x, y) => self.op_8XY3(x as usize, y as usize),
+            Instruction::Op8XY4(x, y) => self.op_8XY4(x as usize, y as usize),
+            Instruction::Op8XY5(x, y) => self.op_8XY5(x as usize, y as usize),
+            Instruction::Op8XY6(x) => self.op_8XY6(x as usize),
+            Instruction::Op8XY7(x, y) => self.op_8XY7(x as usize, y as usize),
+            Instruction::Op8XYE(x) => self.op_8XYE(x as usize),
+            Instruction::Op9XY0(x, y) => self.op_9XY0(x as usize, y as usize),
+            Instruction::OpANNN(nnn) => self.op_ANNN(nnn),
+            Instruction::OpBNNN(nnn) => self.op_BNNN(nnn),
+            Instruction::OpCXNN(x, nn) => self.op_CXNN(x as usize, nn),
+            Instruction::OpDXYN(x, y, n) => self.op_DXYN(x as usize, y as usize, n),
+            Instruction::OpEX9E(x) => self.op_EX9E(x as usize),
+            Instruction::OpEXA1(x) => self.op_EXA1(x as usize),
+            Instruction::OpFX07(x) => self.op_FX07(x as usize),
+            Instruction::OpFX0A(x) => self.op_FX0A(x as usize),
+            Instruction::OpFX15(x) => self.op_FX15(x as usize),
+            Instruction::OpFX18(x) => self.op_FX18(x as usize),
+            Instruction::OpFX1E(x) => self.op_FX1E(x as usize),
+            Instruction::OpFX29(x) => self.op_FX29(x as usize),
+            Instruction::OpFX33(x) => self.op_FX33(x as usize),
+            Instruction::OpFX55(x) => self.op_FX55(x as usize),
+            Instruction::OpFX65(x) => self.op_FX65(x as usize),
+            Instruction::Invalid(opcode) => panic!("Unhandled opcode! {:04X}", opcode),
+        }
+    }
+
     // INSTRUCTIONS ----------------------------------------------------------------
     // Description copied from wikipedia https://en.wikipedia.org/wiki/CHIP-8
     
     // Clears the screen.
     pub fn op_00E0(&mut self) {
-        for i in 0..Chip8::VIDEO_SIZE {
-            self.video[i] = 0;
-        }
+        self.video = [0; Chip8::VIDEO_HEIGHT];
+        self.video_changed = true;
     }
 
     // 	Returns from a subroutine.
@@ -332,46 +502,30 @@ impl Chip8 {
     // I value does not change after the execution of this instruction. As described above, 
     // VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn, and to 0 if that does not happen
     pub fn op_DXYN(&mut self, vx: usize, vy: usize, n: u8) {
-        // sprite is always 8 pixels wide
-        // wrap
-
-        println!("op_DXYN: vx: {}, vy: {}, n: {}", vx, vy, n);
-        println!("self.index_register: {}", self.index_register);
-        println!("");
-
+        // The start position wraps, the sprite itself is clipped at the edges
         let x = self.registers[vx] as usize % Chip8::VIDEO_WIDTH;
         let y = self.registers[vy] as usize % Chip8::VIDEO_HEIGHT;
 
         self.registers[0xF] = 0;
-        for r in 0..n as usize {
-            let sprite_byte = self.memory[self.index_register as usize + r];
-            for c in 0..8 as usize {
-                let sprite_pixel = sprite_byte & (0x80 >> c);
-
-                if sprite_pixel != 0 {
-                    print!("1");
-                } else {
-                    print!("0");
-                }
-
-                if sprite_pixel != 0 {
-                    let screen_pixel_index = ((y + r) as usize * Chip8::VIDEO_WIDTH) + x + c;
-                    if self.video[screen_pixel_index] != 0 {
-                        // Flipped
-                        self.registers[0xF] = 1;
-                    }
-
-                    self.video[screen_pixel_index] ^= 0x1;
-                }
-            } 
-            println!("");
+        self.video_changed = true;
+        for r in 0..(n as usize).min(Chip8::VIDEO_HEIGHT - y) {
+            let address = self.index_register as usize + r;
+            let sprite_byte = self.memory[address];
+            self.sprite_reads[address] = true;
+
+            // Bits pushed past the right edge are dropped by the shift
+            let sprite_row = ((sprite_byte as u64) << (Chip8::VIDEO_WIDTH - 8)) >> x;
+            if self.video[y + r] & sprite_row != 0 {
+                // Flipped
+                self.registers[0xF] = 1;
+            }
+            self.video[y + r] ^= sprite_row;
         }
-
-        println!("");
     }
 
     // Skips the next instruction if the key stored in VX is pressed. (Usually the next instruction is a jump to skip a code block);
     pub fn op_EX9E(&mut self, vx: usize) {
+        self.key_polls[self.registers[vx] as usize] = self.cycles + 1;
         if self.keys[self.registers[vx] as usize] {
             self.pc += 2;
         }
@@ -379,6 +533,7 @@ impl Chip8 {
 
     // Skips the next instruction if the key stored in VX is not pressed. (Usually the next instruction is a jump to skip a code block);
     pub fn op_EXA1(&mut self, vx: usize) {
+        self.key_polls[self.registers[vx] as usize] = self.cycles + 1;
         if !self.keys[self.registers[vx] as usize] {
             self.pc += 2;
         }
@@ -391,6 +546,8 @@ impl Chip8 {
 
     // A key press is awaited, and then stored in VX. (Blocking Operation. All instruction halted until next key event);
     pub fn op_FX0A(&mut self, vx: usize) {
+        // Any key will do
+        self.key_polls = [self.cycles + 1; 16];
         let mut pressed = false;
         for i in 0..16 as usize {
             if self.keys[i] {
@@ -431,18 +588,18 @@ impl Chip8 {
     // the tens digit at location I+1, and the ones digit at location I+2.);
     pub fn op_FX33(&mut self, vx: usize) {
         let mut val = self.registers[vx];
-        self.memory[self.index_register as usize + 2] = val % 10;
+        self.store(self.index_register as usize + 2, val % 10);
         val /= 10;
-        self.memory[self.index_register as usize + 1] = val % 10;
+        self.store(self.index_register as usize + 1, val % 10);
         val /= 10;
-        self.memory[self.index_register as usize] = val % 10;
+        self.store(self.index_register as usize, val % 10);
     }
 
     // Stores from V0 to VX (including VX) in memory, starting at address I. 
     // The offset from I is increased by 1 for each value written, but I itself is left unmodified.
     pub fn op_FX55(&mut self, vx: usize) {
         for i in 0..vx + 1 {
-            self.memory[self.index_register as usize + i] = self.registers[i];
+            self.store(self.index_register as usize + i, self.registers[i]);
         }
     }
 
@@ -456,8 +613,198 @@ impl Chip8 {
 
     // ---------------------------------------------------------------------------------------------------------------------
 
+    // Memory write from an instruction, tracked for self-modifying code detection
+    fn store(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
 
+        if let Some(cache) = self.decode_cache.as_mut() {
+            // The byte is the first or second half of an instruction
+            cache[address] = None;
+            cache[(address + 4095) % 4096] = None;
+        }
+
+        if let Some(smc) = self.smc.as_mut() {
+            if smc.executed[address] {
+                smc.events.push(SmcEvent {
+                    kind: SmcKind::WriteToExecuted,
+                    address: address as u16,
+                    // pc has already moved past the storing instruction
+                    pc: self.pc.wrapping_sub(2),
+                    cycle: self.cycles.saturating_sub(1),
+                });
+            }
+            smc.written[address] = true;
+        }
+    }
+
+
+
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rom: &[u8], cycles: usize) -> Chip8 {
+        let mut chip = Chip8::new(&rom.to_vec());
+        chip.enable_smc_detection();
+        for _ in 0..cycles {
+            chip.cycle();
+        }
+        chip
+    }
+
+    #[test]
+    fn reports_execution_of_written_memory_once() {
+        // I = 208; V0 = 12; V1 = 08; store V0-V1 at 208, which becomes JP 208
+        let mut chip = run(&[0xA2, 0x08, 0x60, 0x12, 0x61, 0x08, 0xF1, 0x55, 0x00, 0x00], 4);
+        assert!(chip.take_smc_events().is_empty());
+        assert!(chip.was_written(0x208) && chip.was_written(0x209));
+
+        chip.cycle();
+        let events = chip.take_smc_events();
+        assert_eq!(events.len(), 2);
+        for (event, address) in events.iter().zip([0x208, 0x209]) {
+            assert!(matches!(event.kind, SmcKind::ExecuteWritten));
+            assert_eq!((event.address, event.pc, event.cycle), (address, 0x208, 4));
+        }
+        assert!(!chip.was_written(0x208));
+
+        chip.cycle();
+        assert!(chip.take_smc_events().is_empty());
+        assert_eq!(chip.get_pc(), 0x208);
+    }
+
+    #[test]
+    fn reports_writes_to_executed_code() {
+        // I = 200; V0 = 12; V1 = 00; store V0-V1 over the first instruction
+        let mut chip = run(&[0xA2, 0x00, 0x60, 0x12, 0x61, 0x00, 0xF1, 0x55], 4);
+        let events = chip.take_smc_events();
+        assert_eq!(events.len(), 2);
+        for (event, address) in events.iter().zip([0x200, 0x201]) {
+            assert!(matches!(event.kind, SmcKind::WriteToExecuted));
+            assert_eq!((event.address, event.pc, event.cycle), (address, 0x206, 3));
+        }
+    }
 
+    #[test]
+    fn records_nothing_while_detection_is_disabled() {
+        let mut chip = Chip8::new(&vec![0xA2, 0x00, 0x60, 0x12, 0x61, 0x00, 0xF1, 0x55]);
+        for _ in 0..4 {
+            chip.cycle();
+        }
+        assert!(chip.take_smc_events().is_empty());
+        assert!(!chip.was_written(0x200));
+    }
 
+    // Runs code at 0x20C to fill the cache, then the program from 0x200
+    fn run_cached(rom: &[u8], warm_up: usize, cycles: usize) -> Chip8 {
+        let mut chip = Chip8::new(&rom.to_vec());
+        chip.enable_decode_cache();
+        chip.set_pc(0x20C);
+        for _ in 0..warm_up {
+            chip.cycle();
+        }
+        chip.set_pc(0x200);
+        for _ in 0..cycles {
+            chip.cycle();
+        }
+        chip
+    }
 
+    #[test]
+    fn decode_cache_is_invalidated_by_fx33() {
+        // I = 20D; V1 = 200; store its digits 2, 0, 0 at 20D-20F; jump to 20C,
+        // where LD V0, FF has become LD V0, 02 and LD V1, 11 has become 0000
+        let rom = [
+            0xA2, 0x0D, 0x61, 0xC8, 0xF1, 0x33, 0x12, 0x0C,
+            0x00, 0x00, 0x00, 0x00, 0x60, 0xFF, 0x61, 0x11,
+        ];
+        let chip = run_cached(&rom, 2, 6);
+        assert_eq!(chip.get_registers()[0], 0x02);
+        assert_eq!(chip.get_registers()[1], 0xC8);
+        assert_eq!(chip.get_pc(), 0x210);
+    }
+
+    #[test]
+    fn decode_cache_is_invalidated_by_fx55() {
+        // I = 20D; V0 = 6A; store V0 at 20D, the second byte of LD V1, 00 at 20C
+        let rom = [
+            0xA2, 0x0D, 0x60, 0x6A, 0xF0, 0x55, 0x12, 0x0C,
+            0x00, 0x00, 0x00, 0x00, 0x61, 0x00,
+        ];
+        let chip = run_cached(&rom, 1, 5);
+        assert_eq!(chip.get_registers()[1], 0x6A);
+    }
+
+    // Draws n rows of the sprite at 0x200 at (x, y), returning VF
+    fn draw(chip: &mut Chip8, x: u8, y: u8, n: u8) -> u8 {
+        chip.op_6XNN(0, x);
+        chip.op_6XNN(1, y);
+        chip.op_ANNN(0x200);
+        chip.op_DXYN(0, 1, n);
+        chip.get_registers()[0xF]
+    }
+
+    #[test]
+    fn draws_sprite_rows_into_packed_rows() {
+        let mut chip = Chip8::new(&vec![0xF0, 0x81]);
+        assert_eq!(draw(&mut chip, 0, 0, 2), 0);
+        assert_eq!(chip.get_video_row(0), 0xF0 << 56);
+        assert_eq!(chip.get_video_row(1), 0x81 << 56);
+
+        draw(&mut chip, 10, 5, 2);
+        assert_eq!(chip.get_video_row(5), 0xF0 << (56 - 10));
+        assert_eq!(chip.get_video_row(6), 0x81 << (56 - 10));
+        assert_eq!(chip.get_video(10, 6), 1);
+        assert_eq!(chip.get_video(11, 6), 0);
+        assert_eq!(chip.get_video(17, 6), 1);
+    }
+
+    #[test]
+    fn clips_sprites_at_the_right_and_bottom_edges() {
+        let mut chip = Chip8::new(&vec![0xFF, 0xFF, 0xFF, 0xFF]);
+        draw(&mut chip, 60, 30, 4);
+        assert_eq!(chip.get_video_row(30), 0xF);
+        assert_eq!(chip.get_video_row(31), 0xF);
+        // Nothing wraps to the left edge or the top
+        assert_eq!(chip.get_video_row(0), 0);
+        assert_eq!(chip.get_video_row(1), 0);
+    }
+
+    #[test]
+    fn wraps_the_start_position() {
+        let mut chip = Chip8::new(&vec![0x80]);
+        draw(&mut chip, 64 + 3, 32 + 2, 1);
+        assert_eq!(chip.get_video(3, 2), 1);
+        assert_eq!(chip.get_video_row(2), 1 << (63 - 3));
+    }
+
+    #[test]
+    fn sets_vf_only_when_a_pixel_is_turned_off() {
+        let mut chip = Chip8::new(&vec![0xC0, 0x30]);
+        assert_eq!(draw(&mut chip, 8, 8, 1), 0);
+        // Touching but not overlapping
+        chip.op_ANNN(0x201);
+        chip.op_DXYN(0, 1, 1);
+        assert_eq!(chip.get_registers()[0xF], 0);
+        assert_eq!(chip.get_video_row(8), 0xF0 << (56 - 8));
+
+        // Overlapping on one row turns its pixels off
+        assert_eq!(draw(&mut chip, 8, 8, 1), 1);
+        assert_eq!(chip.get_video_row(8), 0x30 << (56 - 8));
+        assert_eq!(draw(&mut chip, 20, 8, 1), 0);
+    }
+
+    #[test]
+    fn ignores_collisions_with_clipped_pixels() {
+        let mut chip = Chip8::new(&vec![0xFF, 0xFF]);
+        draw(&mut chip, 0, 0, 1);
+        // The clipped parts would overlap row 0's pixels if they wrapped
+        assert_eq!(draw(&mut chip, 60, 0, 1), 0);
+        assert_eq!(draw(&mut chip, 0, 31, 2), 0);
+        assert_eq!(chip.get_video_row(0), 0xFF << 56 | 0xF);
+    }
 }
@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use crate::chip8::{Chip8, SmcEvent, SmcKind};
+use crate::disasm;
+use crate::symbols::SymbolMap;
+
+pub enum DebugAction {
+    Run,
+    Quit,
+}
+
+// Interactive command-line debugger. Commands are read from stdin whenever
+// execution stops on a breakpoint or after a step.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    // Instructions left before stopping again, None when running freely
+    steps_remaining: Option<u32>,
+    // Stop on self-modifying code
+    break_on_smc: bool,
+    // Reason to stop at the next check, set by notifications between instructions
+    pending_stop: Option<String>,
+}
+
+impl Debugger {
+    pub fn new(breakpoints: &[u16], break_at_start: bool, break_on_smc: bool) -> Debugger {
+        Debugger {
+            breakpoints: breakpoints.iter().cloned().collect(),
+            steps_remaining: if break_at_start { Some(0) } else { None },
+            break_on_smc,
+            pending_stop: None,
+        }
+    }
+
+    // Called with each self-modifying code event after the instruction that caused it
+    pub fn notify_smc(&mut self, event: &SmcEvent, symbols: &SymbolMap) {
+        if self.break_on_smc {
+            if let SmcKind::WriteToExecuted = event.kind {
+                self.pending_stop = Some(describe_smc(event, symbols));
+            }
+        }
+    }
+
+    // Called before each instruction; stops and prompts when needed
+    pub fn check(&mut self, chip: &Chip8, symbols: &SymbolMap) -> DebugAction {
+        let pc = chip.get_pc();
+        let stop = match self.steps_remaining {
+            Some(0) => true,
+            Some(n) => {
+                self.steps_remaining = Some(n - 1);
+                self.breakpoints.contains(&pc)
+            }
+            None => self.breakpoints.contains(&pc),
+        };
+
+        // Stop before running an instruction a store has modified
+        let executes_written = self.break_on_smc
+            && (chip.was_written(pc) || chip.was_written(pc.wrapping_add(1)));
+        let pending_stop = self.pending_stop.take();
+
+        if !stop && !executes_written && pending_stop.is_none() {
+            return DebugAction::Run;
+        }
+
+        if self.breakpoints.contains(&pc) {
+            println!("Breakpoint at {}", symbols.describe(pc));
+        }
+        if let Some(reason) = pending_stop {
+            println!("Self-modifying code: {}", reason);
+        }
+        if executes_written {
+            println!("Self-modifying code: about to execute {}, modified since it was loaded", symbols.describe(pc));
+        }
+        self.prompt(chip, symbols)
+    }
+
+    fn prompt(&mut self, chip: &Chip8, symbols: &SymbolMap) -> DebugAction {
+        println!("{}", disasm::disassemble_at(chip, chip.get_pc(), symbols));
+
+        let stdin = io::stdin();
+        loop {
+            print!("(chip8) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap() == 0 {
+                return DebugAction::Quit;
+            }
+
+            let args: Vec<&str> = line.split_whitespace().collect();
+            if args.is_empty() {
+                continue;
+            }
+
+            match args[0] {
+                "c" | "continue" => {
+                    self.steps_remaining = None;
+                    return DebugAction::Run;
+                }
+                "s" | "step" => {
+                    let count = match args.get(1) {
+                        Some(a) => match a.parse::<u32>() {
+                            Ok(n) if n > 0 => n,
+                            _ => { println!("Invalid step count '{}'", a); continue; }
+                        },
+                        None => 1,
+                    };
+                    self.steps_remaining = Some(count - 1);
+                    return DebugAction::Run;
+                }
+                "b" | "break" => match self.address_arg(&args, 1, symbols) {
+                    Some(a) => {
+                        self.breakpoints.insert(a);
+                        println!("Breakpoint set at {}", symbols.describe(a));
+                    }
+                    None => println!("Usage: break <address|label>"),
+                },
+                "d" | "delete" => match self.address_arg(&args, 1, symbols) {
+                    Some(a) => {
+                        if self.breakpoints.remove(&a) {
+                            println!("Breakpoint removed at {}", symbols.describe(a));
+                        } else {
+                            println!("No breakpoint at {}", symbols.describe(a));
+                        }
+                    }
+                    None => println!("Usage: delete <address|label>"),
+                },
+                "bl" | "breakpoints" => {
+                    for a in &self.breakpoints {
+                        println!("{}", symbols.describe(*a));
+                    }
+                }
+                "r" | "regs" => print_registers(chip),
+                "bt" | "stack" => print_call_stack(chip, symbols),
+                "x" | "memory" => {
+                    let start = match self.address_arg(&args, 1, symbols) {
+                        Some(a) => a,
+                        None => { println!("Usage: memory <address|label> [length]"); continue; }
+                    };
+                    // Past the whole address space it would only repeat
+                    let length = args.get(2).and_then(|a| a.parse::<usize>().ok()).unwrap_or(16);
+                    print_memory(chip, start, length.min(Chip8::MEMORY_SIZE));
+                }
+                "dis" | "disassemble" => {
+                    let start = if args.len() > 1 {
+                        match self.address_arg(&args, 1, symbols) {
+                            Some(a) => a,
+                            None => { println!("Usage: disassemble [address|label] [count]"); continue; }
+                        }
+                    } else {
+                        chip.get_pc()
+                    };
+                    let count = args.get(2).and_then(|a| a.parse::<usize>().ok()).unwrap_or(10);
+                    for i in 0..count.min(Chip8::MEMORY_SIZE / 2) {
+                        let address = (start as usize + i * 2) % Chip8::MEMORY_SIZE;
+                        println!("{}", disasm::disassemble_at(chip, address as u16, symbols));
+                    }
+                }
+                "smc" => {
+                    match args.get(1) {
+                        Some(&"on") => self.break_on_smc = true,
+                        Some(&"off") => self.break_on_smc = false,
+                        _ => {}
+                    }
+                    println!("Break on self-modifying code: {}", if self.break_on_smc { "on" } else { "off" });
+                }
+                "q" | "quit" => return DebugAction::Quit,
+                "h" | "help" => print_help(),
+                _ => println!("Unknown command '{}'. Type 'help' for a list of commands.", args[0]),
+            }
+        }
+    }
+
+    fn address_arg(&self, args: &[&str], i: usize, symbols: &SymbolMap) -> Option<u16> {
+        let address = symbols.resolve(args.get(i)?)?;
+        if address < 0x1000 {
+            Some(address)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn print_registers(chip: &Chip8) {
+    let registers = chip.get_registers();
+    for row in 0..2 {
+        let line: Vec<String> = (0..8)
+            .map(|i| format!("V{:X}={:02X}", row * 8 + i, registers[row * 8 + i]))
+            .collect();
+        println!("{}", line.join(" "));
+    }
+    println!(
+        "I={:03X} PC={:03X} DT={:02X} ST={:02X} cycle={}",
+        chip.get_index_register(),
+        chip.get_pc(),
+        chip.get_delay_timer(),
+        chip.get_sound_timer(),
+        chip.get_cycles()
+    );
+}
+
+// Innermost frame first. The stack holds return addresses, so each caller's
+// frame is the CALL instruction just before its return address.
+pub fn print_call_stack(chip: &Chip8, symbols: &SymbolMap) {
+    println!("#0  {}", symbols.describe(chip.get_pc()));
+    for (depth, ret) in chip.get_stack().iter().rev().enumerate() {
+        println!("#{}  {}", depth + 1, symbols.describe(ret.wrapping_sub(2)));
+    }
+}
+
+// Wraps around the end of memory, length is at most Chip8::MEMORY_SIZE
+fn print_memory(chip: &Chip8, start: u16, length: usize) {
+    for row in (0..length).step_by(16) {
+        let address = (start as usize + row) % Chip8::MEMORY_SIZE;
+        let bytes: Vec<String> = (row..length.min(row + 16))
+            .map(|i| format!("{:02X}", chip.get_memory(((start as usize + i) % Chip8::MEMORY_SIZE) as u16)))
+            .collect();
+        println!("{:03X}: {}", address, bytes.join(" "));
+    }
+}
+
+fn print_help() {
+    println!("Addresses may be numbers (0x2A4, 676), labels or label+offset.");
+    println!("  c, continue                  resume execution");
+    println!("  s, step [n]                  execute n instructions (default 1)");
+    println!("  b, break <addr>              set a breakpoint");
+    println!("  d, delete <addr>             remove a breakpoint");
+    println!("  bl, breakpoints              list breakpoints");
+    println!("  r, regs                      show registers and timers");
+    println!("  bt, stack                    show the call stack");
+    println!("  x, memory <addr> [len]       dump memory (default 16 bytes)");
+    println!("  dis, disassemble [addr] [n]  disassemble n instructions (default 10)");
+    println!("  smc [on|off]                 break on self-modifying code");
+    println!("  q, quit                      exit the emulator");
+}
+
+pub fn describe_smc(event: &SmcEvent, symbols: &SymbolMap) -> String {
+    match event.kind {
+        SmcKind::WriteToExecuted => format!(
+            "cycle {}: {} wrote to already executed {}",
+            event.cycle,
+            symbols.describe(event.pc),
+            symbols.describe(event.address)
+        ),
+        SmcKind::ExecuteWritten => format!(
+            "cycle {}: executed {}, modified since it was loaded",
+            event.cycle,
+            symbols.describe(event.address)
+        ),
+    }
+}
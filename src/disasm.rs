@@ -0,0 +1,167 @@
+use crate::chip8::Chip8;
+use crate::symbols::SymbolMap;
+
+// Mnemonics follow Cowgod's Chip-8 technical reference, with the SCHIP and
+// XO-CHIP extensions
+pub fn disassemble(opcode: u16, symbols: Option<&SymbolMap>) -> String {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+
+    let address = |a: u16| match symbols {
+        Some(s) => s.operand(a),
+        None => format!("{:#05X}", a),
+    };
+
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            0x00C0..=0x00CF => format!("SCD {}", n),
+            0x00D0..=0x00DF => format!("SCU {}", n),
+            0x00FB => "SCR".to_string(),
+            0x00FC => "SCL".to_string(),
+            0x00FD => "EXIT".to_string(),
+            0x00FE => "LOW".to_string(),
+            0x00FF => "HIGH".to_string(),
+            _ => format!("SYS {}", address(nnn)),
+        },
+        0x1 => format!("JP {}", address(nnn)),
+        0x2 => format!("CALL {}", address(nnn)),
+        0x3 => format!("SE V{:X}, {:#04X}", x, nn),
+        0x4 => format!("SNE V{:X}, {:#04X}", x, nn),
+        0x5 => match n {
+            0x0 => format!("SE V{:X}, V{:X}", x, y),
+            0x2 => format!("SAVE V{:X}-V{:X}", x, y),
+            0x3 => format!("LOAD V{:X}-V{:X}", x, y),
+            _ => data(opcode),
+        },
+        0x6 => format!("LD V{:X}, {:#04X}", x, nn),
+        0x7 => format!("ADD V{:X}, {:#04X}", x, nn),
+        0x8 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => data(opcode),
+        },
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {}", address(nnn)),
+        0xB => format!("JP V0, {}", address(nnn)),
+        0xC => format!("RND V{:X}, {:#04X}", x, nn),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match nn {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data(opcode),
+        },
+        0xF => match nn {
+            0x00 if x == 0 => "LD I, LONG".to_string(),
+            0x01 => format!("PLANE {}", x),
+            0x02 if x == 0 => "AUDIO".to_string(),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => data(opcode),
+        },
+        _ => data(opcode),
+    }
+}
+
+fn data(opcode: u16) -> String {
+    format!("DW {:#06X}", opcode)
+}
+
+// One listing line for the instruction at address, e.g.
+// "0x2A4 <draw_player+4>  6A05  LD VA, 0x05  ; main.8o:42"
+pub fn disassemble_at(chip: &Chip8, address: u16, symbols: &SymbolMap) -> String {
+    let opcode = chip.get_opcode(address);
+    let mut line = format!(
+        "{:<28}{:04X}  {}",
+        symbols.describe(address),
+        opcode,
+        disassemble(opcode, Some(symbols))
+    );
+
+    if let Some((file, source_line)) = symbols.source_at(address) {
+        line.push_str(&format!("  ; {}:{}", file, source_line));
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_instructions() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x00EE, "RET"),
+            (0x00C4, "SCD 4"),
+            (0x00FF, "HIGH"),
+            (0x0123, "SYS 0x123"),
+            (0x1234, "JP 0x234"),
+            (0x2ABC, "CALL 0xABC"),
+            (0x3A05, "SE VA, 0x05"),
+            (0x5120, "SE V1, V2"),
+            (0x5122, "SAVE V1-V2"),
+            (0x6BFF, "LD VB, 0xFF"),
+            (0x8124, "ADD V1, V2"),
+            (0x812E, "SHL V1, V2"),
+            (0x9120, "SNE V1, V2"),
+            (0xA300, "LD I, 0x300"),
+            (0xB200, "JP V0, 0x200"),
+            (0xC10F, "RND V1, 0x0F"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xE39E, "SKP V3"),
+            (0xE3A1, "SKNP V3"),
+            (0xF000, "LD I, LONG"),
+            (0xF20A, "LD V2, K"),
+            (0xF233, "LD B, V2"),
+            (0xF255, "LD [I], V2"),
+            (0xF265, "LD V2, [I]"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(disassemble(opcode, None), text, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn shows_undefined_opcodes_as_data() {
+        for opcode in [0x5121, 0x8128, 0x9121, 0xE3A2, 0xF2FF, 0xF100] {
+            assert_eq!(disassemble(opcode, None), format!("DW {:#06X}", opcode));
+        }
+    }
+
+    #[test]
+    fn uses_labels_for_address_operands() {
+        let symbols = SymbolMap::parse("0x300 sprite\n0x200 start main.8o:3").unwrap();
+        assert_eq!(disassemble(0xA300, Some(&symbols)), "LD I, sprite");
+        assert_eq!(disassemble(0x1202, Some(&symbols)), "JP 0x202");
+
+        let chip = Chip8::new(&vec![0x12, 0x00]);
+        assert_eq!(
+            disassemble_at(&chip, 0x200, &symbols),
+            format!("{:<28}1200  JP start  ; main.8o:3", "0x200 <start>")
+        );
+    }
+}
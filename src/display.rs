@@ -1,14 +1,42 @@
 extern crate sdl2;
 
 use crate::chip8;
-use sdl2::pixels::Color;
+use crate::font;
+use crate::frontend::{Renderer, RendererCommand};
+use crate::keypad::Keypad;
+use crate::osd::Osd;
+use crate::palette::Palette;
+use crate::persistence::Persistence;
+use crate::sprite_viewer::{SpriteViewer, ViewerCommand};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::video::FullscreenType;
 
+// Resizable window showing the framebuffer at its 2:1 aspect ratio, letterboxed
+// when the window has another shape.
 pub struct Chip8Display {
-    square_size: u32,
     canvas: sdl2::render::WindowCanvas,
+    // The framebuffer, updated in place every present. Its size never changes,
+    // so it lives as long as the canvas.
+    texture: sdl2::render::Texture,
+    // Scale by whole multiples only, so every emulated pixel is the same size
+    integer_scaling: bool,
+    palette: Palette,
+    persistence: Option<Persistence>,
+    osd: Osd,
+    // Beeper sounding, shown as a border around the window
+    sound: bool,
+    // Shown in a panel on the right of the window
+    keypad: Option<Keypad>,
+    sprite_viewer: Option<SpriteViewer>,
+    // Presentation changed, so the next frame must be drawn
+    stale: bool,
 }
 
 impl Chip8Display {
+    const OSD_SCALE_DIVISOR: u32 = 384;
+
     pub fn new(context: &sdl2::Sdl, title: &str, square_size: u32) -> Chip8Display {
         let video_subsystem = context.video().unwrap();
         let window = video_subsystem.window(
@@ -17,6 +45,7 @@ impl Chip8Display {
             chip8::Chip8::VIDEO_HEIGHT as u32 * square_size
         )
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
 
@@ -24,39 +53,384 @@ impl Chip8Display {
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
+        let texture = canvas.texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                chip8::Chip8::VIDEO_WIDTH as u32,
+                chip8::Chip8::VIDEO_HEIGHT as u32,
+            )
+            .unwrap();
 
         return Chip8Display {
-            square_size,
             canvas,
+            texture,
+            integer_scaling: true,
+            palette: Palette::named("classic").unwrap(),
+            persistence: None,
+            osd: Osd::new(),
+            sound: false,
+            keypad: None,
+            sprite_viewer: None,
+            stale: false,
         };
     }
 
-    pub fn draw(&mut self, chip8: &chip8::Chip8) {
-        // Clear canvas
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-        self.canvas.clear();
+    // Fade pixels out losing decay of their brightness per frame, None to turn them off at once
+    pub fn set_persistence(&mut self, decay: Option<f32>) {
+        self.persistence = decay.map(Persistence::new);
+    }
+
+    // Drawn along with the display and controlled through RendererCommand::SpriteViewer
+    pub fn set_sprite_viewer(&mut self, viewer: SpriteViewer) {
+        self.sprite_viewer = Some(viewer);
+    }
+
+    // In window coordinates, which mouse positions use and which can differ from pixels
+    pub fn window_size(&self) -> (u32, u32) {
+        self.canvas.window().size()
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let mode = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(mode).unwrap();
+    }
+
+    // Shows or hides the keypad, widening or narrowing a windowed display by
+    // the panel so the game keeps its size
+    pub fn toggle_keypad(&mut self) {
+        self.keypad = match self.keypad {
+            Some(_) => None,
+            None => Some(Keypad::new()),
+        };
+        let visible = self.keypad.is_some();
+        let window = self.canvas.window_mut();
+        if window.fullscreen_state() == FullscreenType::Off {
+            let (width, height) = window.size();
+            let width = if visible { width + height / 2 } else { width.saturating_sub(height / 2).max(1) };
+            window.set_size(width, height).unwrap();
+        }
+        self.stale = true;
+    }
+
+    // Switches between integer scaling and stretching to fill the window
+    pub fn toggle_integer_scaling(&mut self) {
+        self.integer_scaling = !self.integer_scaling;
+        self.stale = true;
+    }
+
+    fn draw_osd(&mut self, chip8: &chip8::Chip8) {
+        let (_, window_height) = self.canvas.output_size().unwrap();
+        // Two screen pixels per font pixel at the default window size
+        let scale = (window_height / Chip8Display::OSD_SCALE_DIVISOR).max(1);
+
+        let status = self.osd.status_lines(chip8);
+        if !status.is_empty() {
+            self.draw_text(&status, 0, 0, scale);
+        }
+        if let Some(message) = self.osd.message().map(|m| m.to_string()) {
+            let line_height = (font::GLYPH_HEIGHT as u32 + 1) * scale;
+            let y = window_height.saturating_sub(line_height + 2 * scale);
+            self.draw_text(&[message], 0, y as i32, scale);
+        }
+        self.osd.mark_drawn();
+    }
+
+    // Lines of white text on a translucent box with its top left corner at (x, y)
+    fn draw_text(&mut self, lines: &[String], x: i32, y: i32, scale: u32) {
+        let advance = (font::GLYPH_WIDTH as u32 + 1) * scale;
+        let line_height = (font::GLYPH_HEIGHT as u32 + 1) * scale;
+        let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas.fill_rect(Rect::new(x, y, columns * advance + 2 * scale, lines.len() as u32 * line_height + 2 * scale)).unwrap();
+        self.canvas.set_blend_mode(BlendMode::None);
 
-        // Draw pixels
-        for y in 0..chip8::Chip8::VIDEO_HEIGHT {
-            for x in 0..chip8::Chip8::VIDEO_WIDTH {
-                let pixel = chip8.get_video(x, y);
-                if pixel == 0 {
-                    self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        let mut rects = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            let top = y + (scale + row as u32 * line_height) as i32;
+            for (column, c) in line.chars().enumerate() {
+                let left = x + (scale + column as u32 * advance) as i32;
+                rects.extend(glyph_rects(c, left, top, scale));
+            }
+        }
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        self.canvas.fill_rects(&rects).unwrap();
+    }
+
+    // Keys in a 4x4 grid, pressed ones in the foreground color and ones the
+    // program just read half way between background and foreground
+    fn draw_keypad(&mut self) {
+        let rect = self.keypad_rect();
+        let keypad = match &self.keypad {
+            Some(k) => k,
+            None => return,
+        };
+        let cell = rect.width() / 4;
+        let gap = (cell / 16).max(1);
+        let size = cell.saturating_sub(2 * gap).max(1);
+        let scale = (size / (2 * font::GLYPH_HEIGHT as u32)).max(1);
+        let background = self.palette.color(0);
+        let foreground = self.palette.color(1);
+
+        for (row, keys) in Keypad::LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let (fill, label) = if keypad.is_pressed(key) {
+                    (foreground, background)
+                } else if keypad.is_lit(key) {
+                    (blend(background, foreground, 0.5), foreground)
                 } else {
-                    self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-                }
-                self.canvas.fill_rect(
-                    sdl2::rect::Rect::new(
-                        x as i32 * self.square_size as i32,
-                        y as i32 * self.square_size as i32,
-                        self.square_size,
-                        self.square_size,
-                    )
-                ).unwrap();
+                    (blend(background, foreground, 0.15), foreground)
+                };
+                let x = rect.x() + (column as u32 * cell + gap) as i32;
+                let y = rect.y() + (row as u32 * cell + gap) as i32;
+                self.canvas.set_draw_color(Color::RGB(fill.0, fill.1, fill.2));
+                self.canvas.fill_rect(Rect::new(x, y, size, size)).unwrap();
+
+                let left = x + (size as i32 - (font::GLYPH_WIDTH as u32 * scale) as i32) / 2;
+                let top = y + (size as i32 - (font::GLYPH_HEIGHT as u32 * scale) as i32) / 2;
+                let rects = glyph_rects(char::from_digit(key as u32, 16).unwrap().to_ascii_uppercase(), left, top, scale);
+                self.canvas.set_draw_color(Color::RGB(label.0, label.1, label.2));
+                self.canvas.fill_rects(&rects).unwrap();
             }
         }
+        if let Some(keypad) = self.keypad.as_mut() {
+            keypad.mark_drawn();
+        }
+    }
+
+    // Frame along the window edges in the foreground color
+    fn draw_sound_border(&mut self) {
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let thickness = (window_height / 48).max(2);
+        let (r, g, b) = self.palette.colors[1];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.fill_rects(&[
+            Rect::new(0, 0, window_width, thickness),
+            Rect::new(0, window_height as i32 - thickness as i32, window_width, thickness),
+            Rect::new(0, 0, thickness, window_height),
+            Rect::new(window_width as i32 - thickness as i32, 0, thickness, window_height),
+        ]).unwrap();
+    }
 
+    // Square the keypad is drawn in, centered in a panel half as wide as the window is high
+    fn keypad_rect(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let panel = window_height / 2;
+        let side = (panel * 7 / 8).max(1);
+        Rect::new(
+            window_width as i32 - (panel + side) as i32 / 2,
+            window_height.saturating_sub(side) as i32 / 2,
+            side,
+            side,
+        )
+    }
+
+    fn target_rect(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        target_rect(window_width, window_height, self.keypad.is_some(), self.integer_scaling)
+    }
+}
+
+impl Renderer for Chip8Display {
+    // Uploads the framebuffer to the streaming texture scaled into the window
+    fn present(&mut self, chip8: &chip8::Chip8) {
+        let width = chip8::Chip8::VIDEO_WIDTH;
+        let height = chip8::Chip8::VIDEO_HEIGHT;
+
+        let palette = &self.palette;
+        let persistence = self.persistence.as_ref();
+        self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for y in 0..height {
+                let row = chip8.get_video_row(y);
+                for x in 0..width {
+                    let (r, g, b) = match persistence {
+                        Some(p) => blend(palette.color(0), palette.color(1), p.level(x, y)),
+                        None => palette.color(((row >> (width - 1 - x)) & 1) as u32),
+                    };
+                    let offset = y * pitch + x * 3;
+                    buffer[offset..offset + 3].copy_from_slice(&[r, g, b]);
+                }
+            }
+        }).unwrap();
+
+        // Letterbox in the background color so the borders blend in
+        let target = self.target_rect();
+        let (r, g, b) = self.palette.colors[0];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, target).unwrap();
+        self.draw_keypad();
+        if self.sound {
+            self.draw_sound_border();
+        }
+        self.draw_osd(chip8);
         self.canvas.present();
+        self.stale = false;
     }
+
+    fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).unwrap();
+    }
+
+    // The target rectangle is computed from the window on every draw
+    fn resize(&mut self, _width: u32, _height: u32) {
+        self.stale = true;
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+        self.stale = true;
+    }
+
+    // Pixels fade by emulated frames, however often the display presents
+    fn end_frame(&mut self, chip8: &chip8::Chip8) {
+        if let Some(persistence) = self.persistence.as_mut() {
+            persistence.update(chip8);
+            if persistence.is_fading() {
+                self.stale = true;
+            }
+        }
+    }
+
+    fn update(&mut self, frame: u64, chip8: &chip8::Chip8) {
+        self.osd.update(frame, chip8);
+        if let Some(keypad) = self.keypad.as_mut() {
+            keypad.update(frame, chip8);
+        }
+        if let Some(viewer) = self.sprite_viewer.as_mut() {
+            viewer.draw(chip8);
+        }
+    }
+
+    // Frames keep changing while pixels fade or the OSD updates, even if the video doesn't
+    fn needs_redraw(&self) -> bool {
+        self.stale
+            || self.osd.needs_redraw()
+            || self.keypad.as_ref().is_some_and(|k| k.needs_redraw())
+    }
+
+    fn notify(&mut self, message: &str) {
+        println!("{}", message);
+        self.osd.notify(message);
+    }
+
+    fn command(&mut self, command: RendererCommand, chip8: &chip8::Chip8) {
+        match command {
+            RendererCommand::ToggleFullscreen => self.toggle_fullscreen(),
+            RendererCommand::ToggleScaling => self.toggle_integer_scaling(),
+            RendererCommand::ToggleStats => self.osd.toggle_stats(),
+            RendererCommand::ToggleRegisters => self.osd.toggle_registers(),
+            RendererCommand::ToggleKeypad => self.toggle_keypad(),
+            RendererCommand::SpriteViewer(ViewerCommand::Close) => self.sprite_viewer = None,
+            RendererCommand::SpriteViewer(command) => {
+                if let Some(viewer) = self.sprite_viewer.as_mut() {
+                    viewer.handle(command, chip8);
+                }
+            }
+        }
+    }
+
+    fn set_sound(&mut self, on: bool) {
+        self.sound = on;
+        self.stale = true;
+    }
+
+    fn key_at(&self, x: f32, y: f32) -> Option<u8> {
+        self.keypad.as_ref()?;
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let rect = self.keypad_rect();
+        Keypad::key_at(
+            (x * window_width as f32 - rect.x() as f32) / rect.width() as f32,
+            (y * window_height as f32 - rect.y() as f32) / rect.height() as f32,
+        )
+    }
+}
+
+// Squares covering the lit pixels of a character with its top left corner at (left, top)
+// Largest area of the window left of the keypad with the framebuffer's aspect ratio, centered
+fn target_rect(window_width: u32, window_height: u32, keypad: bool, integer_scaling: bool) -> Rect {
+    let width = chip8::Chip8::VIDEO_WIDTH as u32;
+    let height = chip8::Chip8::VIDEO_HEIGHT as u32;
+    let window_width = if keypad { window_width.saturating_sub(window_height / 2) } else { window_width };
+
+    let (target_width, target_height) = if integer_scaling {
+        let scale = (window_width / width).min(window_height / height).max(1);
+        (width * scale, height * scale)
+    } else if window_width * height > window_height * width {
+        (window_height * width / height, window_height)
+    } else {
+        (window_width, window_width * height / width)
+    };
+
+    Rect::new(
+        (window_width as i32 - target_width as i32) / 2,
+        (window_height as i32 - target_height as i32) / 2,
+        target_width.max(1),
+        target_height.max(1),
+    )
+}
+
+fn glyph_rects(c: char, left: i32, top: i32, scale: u32) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    for (gy, bits) in font::glyph(c).iter().enumerate() {
+        for gx in 0..font::GLYPH_WIDTH {
+            if bits & (0x10 >> gx) != 0 {
+                rects.push(Rect::new(
+                    left + (gx as u32 * scale) as i32,
+                    top + (gy as u32 * scale) as i32,
+                    scale,
+                    scale,
+                ));
+            }
+        }
+    }
+    rects
+}
+
+// Color between background and foreground at brightness level 0 to 1
+fn blend(background: (u8, u8, u8), foreground: (u8, u8, u8), level: f32) -> (u8, u8, u8) {
+    let mix = |b: u8, f: u8| (b as f32 + (f as f32 - b as f32) * level).round() as u8;
+    (mix(background.0, foreground.0), mix(background.1, foreground.1), mix(background.2, foreground.2))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling_uses_whole_multiples_centered() {
+        assert_eq!(target_rect(640, 320, false, true), Rect::new(0, 0, 640, 320));
+        // Odd sizes leave the remainder as a border
+        assert_eq!(target_rect(199, 101, false, true), Rect::new(3, 2, 192, 96));
+        assert_eq!(target_rect(129, 65, false, true), Rect::new(0, 0, 128, 64));
+    }
+
+    #[test]
+    fn stretch_fills_one_side_keeping_the_aspect_ratio() {
+        assert_eq!(target_rect(199, 101, false, false), Rect::new(0, 1, 199, 99));
+        assert_eq!(target_rect(101, 199, false, false), Rect::new(0, 74, 101, 50));
+        assert_eq!(target_rect(301, 100, false, false), Rect::new(50, 0, 200, 100));
+    }
+
+    #[test]
+    fn windows_smaller_than_the_framebuffer() {
+        // Integer scaling never goes below 1x, so the framebuffer is cropped around the center
+        assert_eq!(target_rect(40, 20, false, true), Rect::new(-12, -6, 64, 32));
+        assert_eq!(target_rect(40, 20, false, false), Rect::new(0, 0, 40, 20));
+        assert_eq!(target_rect(1, 1, false, false), Rect::new(0, 0, 1, 1));
+        assert_eq!(target_rect(0, 0, false, false), Rect::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn keypad_panel_takes_half_the_height_from_the_width() {
+        assert_eq!(target_rect(200, 64, true, true), Rect::new(20, 0, 128, 64));
+        assert_eq!(target_rect(200, 80, true, false), Rect::new(0, 0, 160, 80));
+        // No room left of the panel
+        assert_eq!(target_rect(10, 64, true, false), Rect::new(0, 32, 1, 1));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::chip8::Chip8;
+use crate::palette::Palette;
+use crate::sprite_viewer::ViewerCommand;
+
+// Interfaces between the emulation loop in main.rs and a frontend. A backend
+// implements Renderer for output and InputSource for input, and the loop
+// drives both without knowing what they are built on.
+
+// What an input source asks the emulation loop to do
+pub enum InputEvent {
+    // Keypad key 0-F pressed (true) or released
+    Key(u8, bool),
+    // Pointer (mouse or finger) id pressed (true) or released at x, y in
+    // fractions of the output area, for keys the renderer draws
+    Pointer(i64, f32, f32, bool),
+    Quit,
+    TogglePause,
+    AdvanceFrame,
+    AdvanceInstruction,
+    FastForward(bool),
+    SlowMotion,
+    Screenshot,
+    ToggleRecording,
+    NextPalette,
+    ToggleMute,
+    // Output area changed to width x height, in the backend's own units
+    Resized(u32, u32),
+    // Output was lost, e.g. the window was uncovered, and must be drawn again
+    Redraw,
+    // Passed on to the renderer without the loop looking at it
+    Renderer(RendererCommand),
+}
+
+// Presentation options a renderer may support, ignored by those that don't
+pub enum RendererCommand {
+    ToggleFullscreen,
+    ToggleScaling,
+    ToggleStats,
+    ToggleRegisters,
+    ToggleKeypad,
+    SpriteViewer(ViewerCommand),
+}
+
+pub trait Renderer {
+    // Draws the framebuffer
+    fn present(&mut self, chip8: &Chip8);
+
+    fn set_title(&mut self, title: &str);
+
+    fn resize(&mut self, width: u32, height: u32);
+
+    fn set_palette(&mut self, palette: &Palette);
+
+    // Called once per loop iteration, after the frames due have run
+    fn update(&mut self, _frame: u64, _chip8: &Chip8) {}
+
+    // Called after every emulated frame, once the timers have ticked
+    fn end_frame(&mut self, _chip8: &Chip8) {}
+
+    // Whether to present even though the video didn't change
+    fn needs_redraw(&self) -> bool {
+        false
+    }
+
+    // Status message for the user
+    fn notify(&mut self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn command(&mut self, _command: RendererCommand, _chip8: &Chip8) {}
+
+    // Shows whether the beeper is sounding, for players who can't hear it
+    fn set_sound(&mut self, _on: bool) {}
+
+    // Keypad key drawn at x, y in fractions of the output area
+    fn key_at(&self, _x: f32, _y: f32) -> Option<u8> {
+        None
+    }
+}
+
+pub trait InputSource {
+    // Events since the last call, in order
+    fn poll(&mut self) -> Vec<InputEvent>;
+
+    // Rumbles the input devices that can while on. Called every loop iteration,
+    // so a rumble can be renewed for as long as the beeper sounds.
+    fn set_rumble(&mut self, _on: bool) {}
+}
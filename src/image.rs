@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+// 8-bit RGB image used by the export paths
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, color: (u8, u8, u8)) -> Image {
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for _ in 0..width * height {
+            pixels.extend_from_slice(&[color.0, color.1, color.2]);
+        }
+
+        Image { width, height, pixels }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: (u8, u8, u8)) {
+        let i = (y * self.width + x) * 3;
+        self.pixels[i] = color.0;
+        self.pixels[i + 1] = color.1;
+        self.pixels[i + 2] = color.2;
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: (u8, u8, u8)) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let i = (y * self.width + x) * 3;
+        (self.pixels[i], self.pixels[i + 1], self.pixels[i + 2])
+    }
+
+    // Tightly packed RGB rows
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Error creating {}: {}", path, e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| format!("Error writing {}: {}", path, e))
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+
+use crate::chip8::Chip8;
+
+// Scripted keypad input for headless runs, one event per line:
+//
+//     <frame> down|up <key>
+//
+// Frames count from 0 and events apply before that frame runs. Keys are a
+// single hex digit. Blank lines and lines starting with '#' are ignored.
+pub struct InputScript {
+    // (frame, key, pressed), sorted by frame
+    events: Vec<(u64, u8, bool)>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn new() -> InputScript {
+        InputScript {
+            events: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<InputScript, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading input script {}: {}", path, e))?;
+        InputScript::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<InputScript, String> {
+        let mut script = InputScript::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(format!("line {}: expected '<frame> down|up <key>'", i + 1));
+            }
+
+            let frame = match fields[0].parse::<u64>() {
+                Ok(f) => f,
+                Err(_) => return Err(format!("line {}: invalid frame '{}'", i + 1, fields[0])),
+            };
+            let pressed = match fields[1] {
+                "down" => true,
+                "up" => false,
+                other => return Err(format!("line {}: expected down or up, got '{}'", i + 1, other)),
+            };
+            let key = match u8::from_str_radix(fields[2], 16) {
+                Ok(k) if k < 16 => k,
+                _ => return Err(format!("line {}: invalid key '{}'", i + 1, fields[2])),
+            };
+            script.events.push((frame, key, pressed));
+        }
+
+        // Stable, so events on the same frame keep their order
+        script.events.sort_by_key(|e| e.0);
+        Ok(script)
+    }
+
+    // Applies the events due by the start of frame
+    pub fn apply(&mut self, frame: u64, chip8: &mut Chip8) {
+        while let Some((at, key, pressed)) = self.events.get(self.next) {
+            if *at > frame {
+                break;
+            }
+            chip8.set_key(*key, *pressed);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events_and_skips_comments() {
+        let script = InputScript::parse("# start\n\n  5 down A\n10 up a\n0 down 0\n").unwrap();
+        assert_eq!(script.events, vec![(0, 0, true), (5, 0xA, true), (10, 0xA, false)]);
+        assert!(!script.is_finished());
+        assert!(InputScript::new().is_finished());
+    }
+
+    #[test]
+    fn keeps_the_order_of_events_on_the_same_frame() {
+        let script = InputScript::parse("3 down 1\n1 down 2\n3 up 1\n3 down 1").unwrap();
+        assert_eq!(script.events, vec![(1, 2, true), (3, 1, true), (3, 1, false), (3, 1, true)]);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let error = |text| InputScript::parse(text).err().unwrap();
+        assert_eq!(error("1 down"), "line 1: expected '<frame> down|up <key>'");
+        assert_eq!(error("1 down 2 3"), "line 1: expected '<frame> down|up <key>'");
+        assert_eq!(error("# ok\nx down 2"), "line 2: invalid frame 'x'");
+        assert_eq!(error("-1 down 2"), "line 1: invalid frame '-1'");
+        assert_eq!(error("1 press 2"), "line 1: expected down or up, got 'press'");
+        assert_eq!(error("1 down 10"), "line 1: invalid key '10'");
+        assert_eq!(error("1 down G"), "line 1: invalid key 'G'");
+    }
+
+    #[test]
+    fn applies_events_due_by_each_frame() {
+        let mut script = InputScript::parse("0 down 1\n2 down 2\n2 up 1\n4 up 2").unwrap();
+        let mut chip = Chip8::new(&vec![0x12, 0x00]);
+
+        script.apply(0, &mut chip);
+        assert!(chip.get_keys()[1]);
+        script.apply(1, &mut chip);
+        assert!(!chip.get_keys()[2]);
+        // Skipped frames still get their events
+        script.apply(3, &mut chip);
+        assert!(!chip.get_keys()[1] && chip.get_keys()[2]);
+        assert!(!script.is_finished());
+        script.apply(4, &mut chip);
+        assert!(!chip.get_keys()[2]);
+        assert!(script.is_finished());
+    }
+}
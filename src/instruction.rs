@@ -0,0 +1,182 @@
+// Decoded instruction. Variants are named after the Chip8::op_ method that
+// executes them and carry the operands already extracted from the opcode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    Op00E0,
+    Op00EE,
+    Op1NNN(u16),
+    Op2NNN(u16),
+    Op3XNN(u8, u8),
+    Op4XNN(u8, u8),
+    Op5XY0(u8, u8),
+    Op6XNN(u8, u8),
+    Op7XNN(u8, u8),
+    Op8XY0(u8, u8),
+    Op8XY1(u8, u8),
+    Op8XY2(u8, u8),
+    Op8XY3(u8, u8),
+    Op8XY4(u8, u8),
+    Op8XY5(u8, u8),
+    Op8XY6(u8),
+    Op8XY7(u8, u8),
+    Op8XYE(u8),
+    Op9XY0(u8, u8),
+    OpANNN(u16),
+    OpBNNN(u16),
+    OpCXNN(u8, u8),
+    OpDXYN(u8, u8, u8),
+    OpEX9E(u8),
+    OpEXA1(u8),
+    OpFX07(u8),
+    OpFX0A(u8),
+    OpFX15(u8),
+    OpFX18(u8),
+    OpFX1E(u8),
+    OpFX29(u8),
+    OpFX33(u8),
+    OpFX55(u8),
+    OpFX65(u8),
+    Invalid(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Instruction {
+        let n0 = (opcode >> 12) as u8;
+        let n1 = ((opcode >> 8) & 0xF) as u8;
+        let n2 = ((opcode >> 4) & 0xF) as u8;
+        let n3 = (opcode & 0xF) as u8;
+
+        let nnn = opcode & 0xFFF;
+        let nn = (opcode & 0xFF) as u8;
+
+        match n0 {
+            0 => {
+                match n3 {
+                    0 => Instruction::Op00E0,
+                    0xE => Instruction::Op00EE,
+                    _ => Instruction::Invalid(opcode),
+                }
+            }
+            1 => Instruction::Op1NNN(nnn),
+            2 => Instruction::Op2NNN(nnn),
+            3 => Instruction::Op3XNN(n1, nn),
+            4 => Instruction::Op4XNN(n1, nn),
+            5 => Instruction::Op5XY0(n1, n2),
+            6 => Instruction::Op6XNN(n1, nn),
+            7 => Instruction::Op7XNN(n1, nn),
+            8 => {
+                match n3 {
+                    0 => Instruction::Op8XY0(n1, n2),
+                    1 => Instruction::Op8XY1(n1, n2),
+                    2 => Instruction::Op8XY2(n1, n2),
+                    3 => Instruction::Op8XY3(n1, n2),
+                    4 => Instruction::Op8XY4(n1, n2),
+                    5 => Instruction::Op8XY5(n1, n2),
+                    6 => Instruction::Op8XY6(n1),
+                    7 => Instruction::Op8XY7(n1, n2),
+                    0xE => Instruction::Op8XYE(n1),
+                    _ => Instruction::Invalid(opcode),
+                }
+            }
+            9 => Instruction::Op9XY0(n1, n2),
+            0xA => Instruction::OpANNN(nnn),
+            0xB => Instruction::OpBNNN(nnn),
+            0xC => Instruction::OpCXNN(n1, nn),
+            0xD => Instruction::OpDXYN(n1, n2, n3),
+            0xE => {
+                match nn {
+                    0xA1 => Instruction::OpEXA1(n1),
+                    0x9E => Instruction::OpEX9E(n1),
+                    _ => Instruction::Invalid(opcode),
+                }
+            }
+            0xF => {
+                match nn {
+                    0x07 => Instruction::OpFX07(n1),
+                    0x0A => Instruction::OpFX0A(n1),
+                    0x15 => Instruction::OpFX15(n1),
+                    0x18 => Instruction::OpFX18(n1),
+                    0x1E => Instruction::OpFX1E(n1),
+                    0x29 => Instruction::OpFX29(n1),
+                    0x33 => Instruction::OpFX33(n1),
+                    0x55 => Instruction::OpFX55(n1),
+                    0x65 => Instruction::OpFX65(n1),
+                    _ => Instruction::Invalid(opcode),
+                }
+            }
+            _ => Instruction::Invalid(opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The nibble decode Chip8::cycle used before instructions were predecoded,
+    // with None where it panicked
+    fn cycle_decode(opcode: u16) -> Option<Instruction> {
+        let n0 = (opcode >> 12) as u8;
+        let n1 = ((opcode >> 8) & 0xF) as u8;
+        let n2 = ((opcode >> 4) & 0xF) as u8;
+        let n3 = (opcode & 0xF) as u8;
+        let nnn = opcode & 0xFFF;
+        let nn = (n2 << 4) | n3;
+
+        let instruction = match n0 {
+            0 => match n3 {
+                0 => Instruction::Op00E0,
+                0xE => Instruction::Op00EE,
+                _ => return None,
+            },
+            1 => Instruction::Op1NNN(nnn),
+            2 => Instruction::Op2NNN(nnn),
+            3 => Instruction::Op3XNN(n1, nn),
+            4 => Instruction::Op4XNN(n1, nn),
+            5 => Instruction::Op5XY0(n1, n2),
+            6 => Instruction::Op6XNN(n1, nn),
+            7 => Instruction::Op7XNN(n1, nn),
+            8 => match n3 {
+                0 => Instruction::Op8XY0(n1, n2),
+                1 => Instruction::Op8XY1(n1, n2),
+                2 => Instruction::Op8XY2(n1, n2),
+                3 => Instruction::Op8XY3(n1, n2),
+                4 => Instruction::Op8XY4(n1, n2),
+                5 => Instruction::Op8XY5(n1, n2),
+                6 => Instruction::Op8XY6(n1),
+                7 => Instruction::Op8XY7(n1, n2),
+                0xE => Instruction::Op8XYE(n1),
+                _ => return None,
+            },
+            9 => Instruction::Op9XY0(n1, n2),
+            0xA => Instruction::OpANNN(nnn),
+            0xB => Instruction::OpBNNN(nnn),
+            0xC => Instruction::OpCXNN(n1, nn),
+            0xD => Instruction::OpDXYN(n1, n2, n3),
+            0xE if n2 == 0xA && n3 == 0x1 => Instruction::OpEXA1(n1),
+            0xE if n2 == 0x9 && n3 == 0xE => Instruction::OpEX9E(n1),
+            0xF => match (n2, n3) {
+                (0x0, 0x7) => Instruction::OpFX07(n1),
+                (0x0, 0xA) => Instruction::OpFX0A(n1),
+                (0x1, 0x5) => Instruction::OpFX15(n1),
+                (0x1, 0x8) => Instruction::OpFX18(n1),
+                (0x1, 0xE) => Instruction::OpFX1E(n1),
+                (0x2, 0x9) => Instruction::OpFX29(n1),
+                (0x3, 0x3) => Instruction::OpFX33(n1),
+                (0x5, 0x5) => Instruction::OpFX55(n1),
+                (0x6, 0x5) => Instruction::OpFX65(n1),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    #[test]
+    fn decodes_every_opcode_like_the_cycle_decoder() {
+        for opcode in 0..=u16::MAX {
+            let expected = cycle_decode(opcode).unwrap_or(Instruction::Invalid(opcode));
+            assert_eq!(Instruction::decode(opcode), expected, "{:04X}", opcode);
+        }
+    }
+}
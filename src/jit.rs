@@ -0,0 +1,504 @@
+// Dynamic recompiler for x86-64. Straight-line runs of register instructions
+// are translated into native code once and then called directly. Everything
+// else (draws, key waits, timers, calls, stores, and any code the program has
+// written to) runs through Chip8::cycle.
+//
+// Translated blocks are called as
+//     extern "sysv64" fn(registers: *mut u8, index: *mut u16) -> u32
+// with V0-VF at [rdi], I at [rsi], and return the next PC in eax.
+
+use crate::chip8::Chip8;
+
+type BlockFn = unsafe extern "sysv64" fn(*mut u8, *mut u16) -> u32;
+
+#[derive(Clone, Copy)]
+struct Block {
+    offset: usize,
+    instructions: u64,
+    // First address after the block
+    end: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Unknown,
+    Interpret,
+    Block(Block),
+}
+
+enum Emitted {
+    Straight,
+    // Control transfer; the block returns the next PC itself
+    Terminator,
+    Unsupported,
+}
+
+// Memory that blocks are appended to. It is only ever writable or executable,
+// never both: push makes it writable for the copy and executable again after.
+struct CodeBuffer {
+    memory: *mut u8,
+    size: usize,
+    used: usize,
+}
+
+impl CodeBuffer {
+    fn new(size: usize) -> CodeBuffer {
+        let memory = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if memory == libc::MAP_FAILED {
+            panic!("Error mapping memory for the JIT");
+        }
+
+        let buffer = CodeBuffer { memory: memory as *mut u8, size, used: 0 };
+        buffer.protect(libc::PROT_READ | libc::PROT_EXEC);
+        buffer
+    }
+
+    fn protect(&self, protection: libc::c_int) {
+        let result = unsafe { libc::mprotect(self.memory as *mut libc::c_void, self.size, protection) };
+        if result != 0 {
+            panic!("Error changing the protection of the JIT's code memory: {}", std::io::Error::last_os_error());
+        }
+    }
+
+    // Offset of the copied code, None when the buffer is full
+    fn push(&mut self, code: &[u8]) -> Option<usize> {
+        if self.used + code.len() > self.size {
+            return None;
+        }
+
+        let offset = self.used;
+        self.protect(libc::PROT_READ | libc::PROT_WRITE);
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), self.memory.add(offset), code.len());
+        }
+        self.protect(libc::PROT_READ | libc::PROT_EXEC);
+        self.used += code.len();
+        Some(offset)
+    }
+
+    fn function(&self, offset: usize) -> BlockFn {
+        unsafe { std::mem::transmute::<*mut u8, BlockFn>(self.memory.add(offset)) }
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.memory as *mut libc::c_void, self.size);
+        }
+    }
+}
+
+pub struct Jit {
+    code: CodeBuffer,
+    entries: Vec<Entry>,
+    // Number of live blocks covering each byte
+    coverage: Vec<u16>,
+    // Bytes stored to by the program, never translated again
+    modified: Vec<bool>,
+}
+
+impl Jit {
+    const MEMORY_SIZE: usize = 4096;
+    const CODE_SIZE: usize = 1 << 20;
+    const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+    const VF: u8 = 0xF;
+
+    pub fn new() -> Jit {
+        Jit {
+            code: CodeBuffer::new(Jit::CODE_SIZE),
+            entries: vec![Entry::Unknown; Jit::MEMORY_SIZE],
+            coverage: vec![0; Jit::MEMORY_SIZE],
+            modified: vec![false; Jit::MEMORY_SIZE],
+        }
+    }
+
+    // Runs one translated block, or one instruction through the interpreter,
+    // without exceeding budget instructions. Returns the instructions executed.
+    pub fn step(&mut self, chip: &mut Chip8, budget: u64) -> u64 {
+        let pc = chip.get_pc() as usize % Jit::MEMORY_SIZE;
+        if let Entry::Unknown = self.entries[pc] {
+            self.entries[pc] = self.translate(chip, pc);
+        }
+
+        if let Entry::Block(block) = self.entries[pc] {
+            if block.instructions <= budget {
+                let function = self.code.function(block.offset);
+                let (registers, index, pc) = chip.jit_state();
+                let next = unsafe { function(registers.as_mut_ptr(), index) };
+                *pc = next as u16;
+                chip.retire(block.instructions);
+                return block.instructions;
+            }
+        }
+
+        self.interpret(chip);
+        1
+    }
+
+    fn interpret(&mut self, chip: &mut Chip8) {
+        let opcode = chip.get_opcode(chip.get_pc());
+        let x = (opcode >> 8) & 0xF;
+        let stored = match opcode & 0xF0FF {
+            0xF033 => 3,
+            0xF055 => x + 1,
+            _ => 0,
+        };
+        if stored > 0 {
+            self.invalidate(chip.get_index_register() as usize, stored as usize);
+        }
+
+        chip.cycle();
+    }
+
+    // Drops every block overlapping a store and stops translating those bytes
+    fn invalidate(&mut self, start: usize, length: usize) {
+        let mut hits_code = false;
+        for address in start..start + length {
+            let address = address % Jit::MEMORY_SIZE;
+            self.modified[address] = true;
+            hits_code |= self.coverage[address] > 0;
+        }
+        if !hits_code {
+            return;
+        }
+
+        for address in 0..Jit::MEMORY_SIZE {
+            if let Entry::Block(block) = self.entries[address] {
+                if address < start + length && start < block.end {
+                    self.entries[address] = Entry::Unknown;
+                    for covered in address..block.end {
+                        self.coverage[covered] -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn translate(&mut self, chip: &Chip8, start: usize) -> Entry {
+        let mut code = Vec::new();
+        let mut address = start;
+        let mut instructions = 0;
+
+        loop {
+            if instructions == Jit::MAX_BLOCK_INSTRUCTIONS
+                || address + 1 >= Jit::MEMORY_SIZE
+                || self.modified[address]
+                || self.modified[address + 1] {
+                emit_exit(&mut code, address as u16);
+                break;
+            }
+
+            match emit(&mut code, chip.get_opcode(address as u16), address as u16) {
+                Emitted::Straight => {
+                    instructions += 1;
+                    address += 2;
+                }
+                Emitted::Terminator => {
+                    instructions += 1;
+                    address += 2;
+                    break;
+                }
+                Emitted::Unsupported => {
+                    emit_exit(&mut code, address as u16);
+                    break;
+                }
+            }
+        }
+
+        if instructions == 0 {
+            return Entry::Interpret;
+        }
+
+        let offset = match self.code.push(&code) {
+            Some(offset) => offset,
+            None => {
+                // Out of code space, start over
+                self.code.used = 0;
+                self.entries.iter_mut().for_each(|e| *e = Entry::Unknown);
+                self.coverage.iter_mut().for_each(|c| *c = 0);
+                self.code.push(&code).unwrap()
+            }
+        };
+
+        for covered in start..address {
+            self.coverage[covered] += 1;
+        }
+
+        Entry::Block(Block { offset, instructions: instructions as u64, end: address })
+    }
+}
+
+// Appends the translation of one instruction. Each sequence reproduces the
+// matching Chip8::op_ method, including the order VF and VX are written in.
+fn emit(code: &mut Vec<u8>, opcode: u16, address: u16) -> Emitted {
+    let x = ((opcode >> 8) & 0xF) as u8;
+    let y = ((opcode >> 4) & 0xF) as u8;
+    let n = (opcode & 0xF) as u8;
+    let nn = (opcode & 0xFF) as u8;
+    let nnn = opcode & 0xFFF;
+    let next = address.wrapping_add(2);
+
+    match opcode >> 12 {
+        0x1 => {
+            emit_exit(code, nnn);
+            Emitted::Terminator
+        }
+        0x3 | 0x4 => {
+            // cmp byte [rdi+x], nn
+            code.extend_from_slice(&[0x80, 0x7F, x, nn]);
+            emit_skip(code, next, opcode >> 12 == 0x3);
+            Emitted::Terminator
+        }
+        0x5 | 0x9 if n == 0 => {
+            // movzx edx, byte [rdi+x]; cmp dl, [rdi+y]
+            code.extend_from_slice(&[0x0F, 0xB6, 0x57, x, 0x3A, 0x57, y]);
+            emit_skip(code, next, opcode >> 12 == 0x5);
+            Emitted::Terminator
+        }
+        0x6 => {
+            // mov byte [rdi+x], nn
+            code.extend_from_slice(&[0xC6, 0x47, x, nn]);
+            Emitted::Straight
+        }
+        0x7 => {
+            // add byte [rdi+x], nn
+            code.extend_from_slice(&[0x80, 0x47, x, nn]);
+            Emitted::Straight
+        }
+        0x8 => {
+            match n {
+                0x0 => {
+                    load_eax(code, y);
+                    store_al(code, x);
+                }
+                0x1..=0x3 => {
+                    // or/and/xor byte [rdi+x], al
+                    let op = [0x08, 0x20, 0x30][n as usize - 1];
+                    load_eax(code, y);
+                    code.extend_from_slice(&[op, 0x47, x]);
+                }
+                0x4 => {
+                    load_eax(code, x);
+                    load_ecx(code, y);
+                    // add eax, ecx; cmp eax, 0xFF; seta dl
+                    code.extend_from_slice(&[0x01, 0xC8, 0x3D, 0xFF, 0x00, 0x00, 0x00, 0x0F, 0x97, 0xC2]);
+                    store_dl(code, Jit::VF);
+                    store_al(code, x);
+                }
+                0x5 | 0x7 => {
+                    load_eax(code, x);
+                    load_ecx(code, y);
+                    // cmp eax, ecx (VX > VY) or cmp ecx, eax (VY > VX); seta dl
+                    let compare = if n == 0x5 { 0xC8 } else { 0xC1 };
+                    code.extend_from_slice(&[0x39, compare, 0x0F, 0x97, 0xC2]);
+                    store_dl(code, Jit::VF);
+                    // VX = VX - VY, reloaded since VF may be either operand
+                    load_eax(code, x);
+                    load_ecx(code, y);
+                    code.extend_from_slice(&[0x29, 0xC8]);
+                    store_al(code, x);
+                }
+                0x6 => {
+                    // and al, 1
+                    load_eax(code, x);
+                    code.extend_from_slice(&[0x24, 0x01]);
+                    store_al(code, Jit::VF);
+                    // shr al, 1
+                    load_eax(code, x);
+                    code.extend_from_slice(&[0xD0, 0xE8]);
+                    store_al(code, x);
+                }
+                0xE => {
+                    // and al, 0x8; shr al, 7
+                    load_eax(code, x);
+                    code.extend_from_slice(&[0x24, 0x08, 0xC0, 0xE8, 0x07]);
+                    store_al(code, Jit::VF);
+                    // shl al, 1
+                    load_eax(code, x);
+                    code.extend_from_slice(&[0xD0, 0xE0]);
+                    store_al(code, x);
+                }
+                _ => return Emitted::Unsupported,
+            }
+            Emitted::Straight
+        }
+        0xA => {
+            // mov word [rsi], nnn
+            code.extend_from_slice(&[0x66, 0xC7, 0x06]);
+            code.extend_from_slice(&nnn.to_le_bytes());
+            Emitted::Straight
+        }
+        0xF if nn == 0x1E => {
+            // add word [rsi], ax
+            load_eax(code, x);
+            code.extend_from_slice(&[0x66, 0x01, 0x06]);
+            Emitted::Straight
+        }
+        0xF if nn == 0x29 => {
+            // lea eax, [rax+rax*4]; add eax, FONTSET_START_ADDRESS; mov word [rsi], ax
+            load_eax(code, x);
+            code.extend_from_slice(&[0x8D, 0x04, 0x80, 0x05, 0x50, 0x00, 0x00, 0x00, 0x66, 0x89, 0x06]);
+            Emitted::Straight
+        }
+        _ => Emitted::Unsupported,
+    }
+}
+
+// movzx eax, byte [rdi+register]
+fn load_eax(code: &mut Vec<u8>, register: u8) {
+    code.extend_from_slice(&[0x0F, 0xB6, 0x47, register]);
+}
+
+// movzx ecx, byte [rdi+register]
+fn load_ecx(code: &mut Vec<u8>, register: u8) {
+    code.extend_from_slice(&[0x0F, 0xB6, 0x4F, register]);
+}
+
+// mov byte [rdi+register], al
+fn store_al(code: &mut Vec<u8>, register: u8) {
+    code.extend_from_slice(&[0x88, 0x47, register]);
+}
+
+// mov byte [rdi+register], dl
+fn store_dl(code: &mut Vec<u8>, register: u8) {
+    code.extend_from_slice(&[0x88, 0x57, register]);
+}
+
+// Returns next, or the address after it when the preceding compare set ZF
+// (skip_if_equal) or cleared it (!skip_if_equal)
+fn emit_skip(code: &mut Vec<u8>, next: u16, skip_if_equal: bool) {
+    // mov eax, next; mov ecx, next + 2; cmove/cmovne eax, ecx; ret
+    code.push(0xB8);
+    code.extend_from_slice(&(next as u32).to_le_bytes());
+    code.push(0xB9);
+    code.extend_from_slice(&(next.wrapping_add(2) as u32).to_le_bytes());
+    code.extend_from_slice(&[0x0F, if skip_if_equal { 0x44 } else { 0x45 }, 0xC1, 0xC3]);
+}
+
+// mov eax, pc; ret
+fn emit_exit(code: &mut Vec<u8>, pc: u16) {
+    code.push(0xB8);
+    code.extend_from_slice(&(pc as u32).to_le_bytes());
+    code.push(0xC3);
+}
+
+// Runs the ROM on the interpreter and through the JIT in lockstep, comparing
+// the whole machine state after every block. Returns the blocks checked.
+pub fn verify(rom: &Vec<u8>, instructions: u64, seed: u64) -> Result<u64, String> {
+    let mut reference = Chip8::new(rom);
+    let mut translated = Chip8::new(rom);
+    reference.seed_rng(seed);
+    translated.seed_rng(seed);
+
+    let mut jit = Jit::new();
+    let mut executed = 0;
+    let mut blocks = 0;
+    while executed < instructions {
+        let start = translated.get_pc();
+        let count = jit.step(&mut translated, instructions - executed);
+        for _ in 0..count {
+            reference.cycle();
+        }
+        executed += count;
+        blocks += 1;
+
+        if let Some(difference) = compare(&reference, &translated) {
+            return Err(format!(
+                "Mismatch after {} instructions, in the block at {:03X} ({} instructions): {}",
+                executed, start, count, difference
+            ));
+        }
+    }
+
+    Ok(blocks)
+}
+
+fn compare(reference: &Chip8, translated: &Chip8) -> Option<String> {
+    if reference.get_pc() != translated.get_pc() {
+        return Some(format!("PC {:03X} != {:03X}", reference.get_pc(), translated.get_pc()));
+    }
+    if reference.get_index_register() != translated.get_index_register() {
+        return Some(format!("I {:03X} != {:03X}", reference.get_index_register(), translated.get_index_register()));
+    }
+    for i in 0..16 {
+        let (a, b) = (reference.get_registers()[i], translated.get_registers()[i]);
+        if a != b {
+            return Some(format!("V{:X} {:02X} != {:02X}", i, a, b));
+        }
+    }
+    if reference.get_stack() != translated.get_stack() {
+        return Some(format!("stack {:03X?} != {:03X?}", reference.get_stack(), translated.get_stack()));
+    }
+    if reference.get_delay_timer() != translated.get_delay_timer()
+        || reference.get_sound_timer() != translated.get_sound_timer() {
+        return Some("timers differ".to_string());
+    }
+    for address in 0..Jit::MEMORY_SIZE as u16 {
+        if reference.get_memory(address) != translated.get_memory(address) {
+            return Some(format!("memory at {:03X} differs", address));
+        }
+    }
+    for y in 0..Chip8::VIDEO_HEIGHT {
+        if reference.get_video_row(y) != translated.get_video_row(y) {
+            return Some(format!("video row {} differs", y));
+        }
+    }
+    None
+}
+
+#[cfg(all(test, feature = "jit"))]
+mod tests {
+    use super::*;
+    use crate::bench::SUITE;
+
+    #[test]
+    fn matches_the_interpreter_on_the_bench_suite() {
+        for (name, rom) in SUITE.iter() {
+            if let Err(e) = verify(&rom.to_vec(), 20_000, 1) {
+                panic!("{}: {}", name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_interpreter_on_self_modifying_code() {
+        // FX55 rewrites the operand of the LD V1 at 204 with V0
+        let store = vec![
+            0x60, 0x00, // 200 LD V0, 0
+            0x70, 0x01, // 202 ADD V0, 1
+            0x61, 0x05, // 204 LD V1, 5
+            0xA2, 0x05, // 206 LD I, 205
+            0xF0, 0x55, // 208 LD [I], V0
+            0x12, 0x02, // 20A JP 202
+        ];
+        // FX33 rewrites the operand of the ADD at 20E and the CLS at 210, which
+        // stays a CLS (0T00) because V0 is always a multiple of 10
+        let bcd = vec![
+            0x60, 0x00, // 200 LD V0, 0
+            0x70, 0x0A, // 202 ADD V0, 10
+            0x40, 0xFA, // 204 SNE V0, 250
+            0x60, 0x00, // 206 LD V0, 0
+            0xA2, 0x0F, // 208 LD I, 20F
+            0xF0, 0x33, // 20A LD B, V0
+            0x61, 0x00, // 20C LD V1, 0
+            0x71, 0x00, // 20E ADD V1, 0
+            0x00, 0xE0, // 210 CLS
+            0x12, 0x02, // 212 JP 202
+        ];
+        for rom in [store, bcd].iter() {
+            if let Err(e) = verify(rom, 20_000, 1) {
+                panic!("{}", e);
+            }
+        }
+    }
+}
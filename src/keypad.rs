@@ -0,0 +1,154 @@
+use crate::chip8::Chip8;
+
+// On-screen hex keypad shown by Chip8Display next to the game. Keys light up
+// for a moment whenever the program reads them, so players can see which
+// ones a game uses, and can be pressed with the mouse or a touchscreen. Only
+// the state is kept here; the display lays it out and draws it.
+pub struct Keypad {
+    // Last seen Chip8::get_key_polls, a change means the key was read again
+    polls: [u64; 16],
+    // Frame until which each key stays lit
+    lit_until: [u64; 16],
+    keys: [bool; 16],
+    frame: u64,
+
+    // Shown state changed since the last draw
+    dirty: bool,
+}
+
+impl Keypad {
+    // Rows of keys as on the COSMAC VIP
+    pub const LAYOUT: [[u8; 4]; 4] = [
+        [0x1, 0x2, 0x3, 0xC],
+        [0x4, 0x5, 0x6, 0xD],
+        [0x7, 0x8, 0x9, 0xE],
+        [0xA, 0x0, 0xB, 0xF],
+    ];
+    // Long enough to see keys a game only checks now and then
+    const LIT_FRAMES: u64 = 10;
+
+    pub fn new() -> Keypad {
+        Keypad {
+            polls: [0; 16],
+            lit_until: [0; 16],
+            keys: [false; 16],
+            frame: 0,
+            dirty: true,
+        }
+    }
+
+    // Called every loop with the emulated frame count, which can skip ahead
+    // several frames when catching up or fast-forwarding
+    pub fn update(&mut self, frame: u64, chip8: &Chip8) {
+        let last_frame = self.frame;
+        self.frame = frame;
+        for (key, &poll) in chip8.get_key_polls().iter().enumerate() {
+            if poll != self.polls[key] {
+                self.polls[key] = poll;
+                self.dirty |= self.lit_until[key] <= frame;
+                self.lit_until[key] = frame + Keypad::LIT_FRAMES;
+            } else if last_frame < self.lit_until[key] && self.lit_until[key] <= frame {
+                // Went dark since the last update
+                self.dirty = true;
+            }
+        }
+        if chip8.get_keys() != &self.keys {
+            self.keys = *chip8.get_keys();
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_lit(&self, key: u8) -> bool {
+        self.lit_until[key as usize] > self.frame
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize]
+    }
+
+    // Key under a point given in fractions of the keypad's width and height
+    pub fn key_at(x: f32, y: f32) -> Option<u8> {
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return None;
+        }
+        Some(Keypad::LAYOUT[(y * 4.0) as usize][(x * 4.0) as usize])
+    }
+
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_drawn(&mut self) {
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Machine that has just read key 5
+    fn polled_key_5() -> Chip8 {
+        let mut chip = Chip8::new(&vec![]);
+        chip.op_6XNN(0, 0x5);
+        chip.op_EX9E(0);
+        chip
+    }
+
+    #[test]
+    fn lights_read_keys_for_a_while() {
+        let chip = polled_key_5();
+        let mut keypad = Keypad::new();
+        keypad.update(1, &chip);
+        assert!(keypad.is_lit(0x5));
+        assert!(!keypad.is_lit(0x6));
+        assert!(keypad.needs_redraw());
+        keypad.mark_drawn();
+
+        keypad.update(1 + Keypad::LIT_FRAMES - 1, &chip);
+        assert!(keypad.is_lit(0x5));
+        assert!(!keypad.needs_redraw());
+        keypad.update(1 + Keypad::LIT_FRAMES, &chip);
+        assert!(!keypad.is_lit(0x5));
+        assert!(keypad.needs_redraw());
+        keypad.mark_drawn();
+        keypad.update(1 + Keypad::LIT_FRAMES + 1, &chip);
+        assert!(!keypad.needs_redraw());
+    }
+
+    #[test]
+    fn redraws_a_key_that_went_dark_in_a_skipped_frame() {
+        let chip = polled_key_5();
+        let mut keypad = Keypad::new();
+        keypad.update(1, &chip);
+        keypad.mark_drawn();
+
+        // Catching up jumps straight past the frame the key goes dark in
+        keypad.update(1 + Keypad::LIT_FRAMES + 3, &chip);
+        assert!(!keypad.is_lit(0x5));
+        assert!(keypad.needs_redraw());
+    }
+
+    #[test]
+    fn redraws_when_pressed_keys_change() {
+        let mut chip = Chip8::new(&vec![]);
+        let mut keypad = Keypad::new();
+        keypad.update(0, &chip);
+        keypad.mark_drawn();
+        chip.set_key(0xA, true);
+        keypad.update(1, &chip);
+        assert!(keypad.is_pressed(0xA));
+        assert!(keypad.needs_redraw());
+    }
+
+    #[test]
+    fn finds_keys_in_the_cosmac_layout() {
+        assert_eq!(Keypad::key_at(0.0, 0.0), Some(0x1));
+        assert_eq!(Keypad::key_at(0.99, 0.0), Some(0xC));
+        assert_eq!(Keypad::key_at(0.3, 0.6), Some(0x8));
+        assert_eq!(Keypad::key_at(0.25, 0.75), Some(0x0));
+        assert_eq!(Keypad::key_at(0.99, 0.99), Some(0xF));
+        assert_eq!(Keypad::key_at(1.0, 0.5), None);
+        assert_eq!(Keypad::key_at(0.5, -0.1), None);
+    }
+}
@@ -7,22 +7,71 @@ use std::io::Stdout;
 use std::fs::File;
 use std::env;
 
+mod analyzer;
+mod beeper;
+mod bench;
 mod chip8;
+mod debugger;
+mod disasm;
 mod display;
+mod font;
+mod frontend;
+mod image;
+mod input_script;
+mod instruction;
+#[cfg(feature = "jit")]
+mod jit;
+mod keypad;
+mod options;
+mod osd;
+mod palette;
+mod persistence;
+mod profiler;
+mod recompiler;
+mod recorder;
+mod scheduler;
+mod screenshot;
+mod sdl_input;
+mod sprite_viewer;
+mod sprites;
+mod symbols;
+mod tui;
+mod wav;
 
-use std::time::Duration;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use frontend::InputEvent;
 
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let options = options::Options::parse(&args);
 
-    if args.len() != 2 {
-        panic!("Usage: [file_name]. Got {:?}", args);
+    let symbols = match &options.symbols_path {
+        Some(path) => match symbols::SymbolMap::load(path) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        },
+        None => symbols::SymbolMap::new(),
+    };
+
+    let breakpoints: Vec<u16> = options.breakpoints.iter().map(|b| match symbols.resolve(b) {
+        Some(a) => a,
+        None => panic!("Unknown breakpoint address or label {}", b),
+    }).collect();
+    let mut debugger = if options.debug || options.break_on_smc || !breakpoints.is_empty() {
+        Some(debugger::Debugger::new(&breakpoints, options.debug, options.break_on_smc))
+    } else {
+        None
+    };
+    let mut profiler = if options.profile { Some(profiler::Profiler::new()) } else { None };
+
+    if let options::Command::Bench = options.command {
+        if options.rom_path.is_empty() {
+            print!("{}", bench::suite(options.bench_instructions));
+            return;
+        }
     }
 
-    let file_name = &args[1];
+    let file_name = &options.rom_path;
     let mut file = match File::open(file_name) {
         Ok(f) => f,
         Err(e) => panic!("Error opening file {}: {}", file_name, e),
@@ -31,78 +80,527 @@ fn main() {
     let mut buffer: Vec<u8> = Vec::new();
     file.read_to_end(&mut buffer).unwrap();
 
+    match options.command {
+        options::Command::Analyze => {
+            print!("{}", analyzer::Analysis::run(&buffer).report(&symbols));
+            return;
+        }
+        options::Command::Bench => {
+            print!("{}", bench::run(&buffer, options.bench_instructions));
+            return;
+        }
+        options::Command::VerifyJit => {
+            verify_jit(&buffer, options.bench_instructions);
+            return;
+        }
+        options::Command::Recompile => {
+            let rom_name = std::path::Path::new(file_name).file_name().unwrap().to_string_lossy();
+            let source = recompiler::Recompiler::new(&buffer).generate(&rom_name, &symbols);
+            match &options.out_path {
+                Some(path) => match File::create(path).and_then(|mut f| f.write_all(source.as_bytes())) {
+                    Ok(()) => {}
+                    Err(e) => panic!("Error writing {}: {}", path, e),
+                },
+                None => print!("{}", source),
+            }
+            return;
+        }
+        options::Command::Run => {}
+    }
+
     let mut chip = chip8::Chip8::new(&buffer);
-    let sdl_context = sdl2::init().unwrap();
-    let mut display = display::Chip8Display::new(&sdl_context, "Chip8", 24); 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    if options.smc || options.break_on_smc {
+        chip.enable_smc_detection();
+    }
+    if options.decode_cache {
+        chip.enable_decode_cache();
+    }
+
+    let rom_stem = std::path::Path::new(file_name).file_stem().unwrap().to_string_lossy().to_string();
+    if options.headless {
+        run_headless(&mut chip, &options, &symbols, &mut debugger, &mut profiler, &rom_stem);
+        if let Some(profiler) = profiler {
+            print!("{}", profiler.report(&symbols));
+        }
+        return;
+    }
+
+    match options.frontend {
+        options::Frontend::Sdl => {
+            let sdl_context = sdl2::init().unwrap();
+            let beeper = load_beeper(Some(&sdl_context), &options);
+            let mut display = display::Chip8Display::new(&sdl_context, "Chip8", options.scale);
+            if options.stretch {
+                display.toggle_integer_scaling();
+            }
+            if options.keypad {
+                display.toggle_keypad();
+            }
+            if options.fullscreen {
+                display.toggle_fullscreen();
+            }
+            display.set_persistence(options.persistence);
+            let mut viewer_window = None;
+            if let Some(range) = &options.sprites {
+                let sheet = match sprites::SpriteSheet::parse(range, |a| symbols.resolve(a)) {
+                    Some(s) => s,
+                    None => panic!("Invalid sprite range {}, expected START:END[:HEIGHT]", range),
+                };
+                let viewer = sprite_viewer::SpriteViewer::new(&sdl_context, sheet, &rom_stem);
+                viewer_window = Some(viewer.window_id());
+                display.set_sprite_viewer(viewer);
+            }
+            let mut input = sdl_input::SdlInput::new(&sdl_context, display.window_size(), viewer_window);
+            if options.sound_indicator {
+                if let Err(e) = input.enable_rumble(&sdl_context) {
+                    println!("No controller rumble: {}", e);
+                }
+            }
+            run_frontend(&mut chip, &mut display, &mut input, beeper, &options, &symbols, &mut debugger, &mut profiler, &rom_stem);
+        }
+        options::Frontend::Tui => {
+            if debugger.is_some() {
+                panic!("The debugger reads the terminal and needs the SDL frontend");
+            }
+            // Only for audio, before the terminal is taken over so errors can be read.
+            // Without it the tone is still generated, e.g. for --wav.
+            let sdl_context = if options.mute {
+                None
+            } else {
+                match sdl2::init() {
+                    Ok(c) => Some(c),
+                    Err(e) => {
+                        println!("No audio: {}", e);
+                        None
+                    }
+                }
+            };
+            let beeper = load_beeper(sdl_context.as_ref(), &options);
+            let mut tui = tui::Tui::new();
+            let mut input = tui::TuiInput::new(tui.get_release_events());
+            run_frontend(&mut chip, &mut tui, &mut input, beeper, &options, &symbols, &mut debugger, &mut profiler, &rom_stem);
+        }
+    }
+
+    if let Some(profiler) = profiler {
+        print!("{}", profiler.report(&symbols));
+    }
+}
+
+// Main loop shared by the interactive frontends
+#[allow(clippy::too_many_arguments)]
+fn run_frontend(
+    chip: &mut chip8::Chip8,
+    renderer: &mut dyn frontend::Renderer,
+    input: &mut dyn frontend::InputSource,
+    mut beeper: beeper::Beeper,
+    options: &options::Options,
+    symbols: &symbols::SymbolMap,
+    debugger: &mut Option<debugger::Debugger>,
+    profiler: &mut Option<profiler::Profiler>,
+    rom_stem: &str,
+) {
+    let mut palette = load_palette(options);
+    renderer.set_palette(&palette);
+    let mut scheduler = scheduler::Scheduler::new(options.ips);
+    // Run one instruction while paused
+    let mut step_instruction = false;
+    // Scheduler state currently shown in the title
+    let mut title_state = String::new();
+    renderer.set_title("Chip8");
+    // Frames run so far, for screenshot names
+    let mut frame: u64 = 0;
+    let mut recorder = options.record_path.as_ref().map(|path| {
+        match recorder::Recorder::start(path, &palette, options.record_scale) {
+            Ok(r) => r,
+            Err(e) => panic!("{}", e),
+        }
+    });
+    let mut wav = start_wav(options, &beeper);
+    // Sound state last passed to set_sound
+    let mut sound_shown = false;
+    // Keys held down on the renderer's keypad by each pointer
+    let mut pointer_keys: Vec<(i64, u8)> = Vec::new();
+    // Draw the first frame even if the program doesn't touch the screen
+    let mut redraw = true;
 
     'running: loop {
-        // Input handling
-        /*
-        Keypad       Keyboard
-        +-+-+-+-+    +-+-+-+-+
-        |1|2|3|C|    |1|2|3|4|
-        +-+-+-+-+    +-+-+-+-+
-        |4|5|6|D|    |Q|W|E|R|
-        +-+-+-+-+ => +-+-+-+-+
-        |7|8|9|E|    |A|S|D|F|
-        +-+-+-+-+    +-+-+-+-+
-        |A|0|B|F|    |Z|X|C|V|
-        +-+-+-+-+    +-+-+-+-+
-        */
-        for event in event_pump.poll_iter() {
-            // Input handling
+        for event in input.poll() {
             match event {
-                Event::Quit {..} => break 'running,
-                Event::KeyDown { keycode, .. } =>
-                    match keycode {
-                        Some(Keycode::Escape) => break 'running,
-                        Some(Keycode::X) => chip.set_key(0x0, true),
-                        Some(Keycode::Num1) => chip.set_key(0x1, true),
-                        Some(Keycode::Num2) => chip.set_key(0x2, true),
-                        Some(Keycode::Num3) => chip.set_key(0x3, true),
-                        Some(Keycode::Q) => chip.set_key(0x4, true),
-                        Some(Keycode::W) => chip.set_key(0x5, true),
-                        Some(Keycode::E) => chip.set_key(0x6, true),
-                        Some(Keycode::A) => chip.set_key(0x7, true),
-                        Some(Keycode::S) => chip.set_key(0x8, true),
-                        Some(Keycode::D) => chip.set_key(0x9, true),
-                        Some(Keycode::Z) => chip.set_key(0xA, true),
-                        Some(Keycode::C) => chip.set_key(0xB, true),
-                        Some(Keycode::Num4) => chip.set_key(0xC, true),
-                        Some(Keycode::R) => chip.set_key(0xD, true),
-                        Some(Keycode::F) => chip.set_key(0xE, true),
-                        Some(Keycode::V) => chip.set_key(0xF, true),
-                        _ => {},
+                InputEvent::Key(key, pressed) => chip.set_key(key, pressed),
+                InputEvent::Pointer(id, x, y, true) => {
+                    if let Some(key) = renderer.key_at(x, y) {
+                        chip.set_key(key, true);
+                        pointer_keys.push((id, key));
+                    }
+                }
+                // The key pressed goes up wherever the pointer is released
+                InputEvent::Pointer(id, _, _, false) => {
+                    if let Some(i) = pointer_keys.iter().position(|p| p.0 == id) {
+                        let (_, key) = pointer_keys.remove(i);
+                        if !pointer_keys.iter().any(|p| p.1 == key) {
+                            chip.set_key(key, false);
+                        }
                     }
+                }
+                InputEvent::Quit => break 'running,
+                InputEvent::TogglePause => scheduler.toggle_pause(),
+                InputEvent::AdvanceFrame => scheduler.advance_frame(),
+                InputEvent::AdvanceInstruction => {
+                    if !scheduler.is_paused() {
+                        scheduler.toggle_pause();
+                    }
+                    step_instruction = true;
+                }
+                InputEvent::FastForward(on) => scheduler.set_fast_forward(on),
+                InputEvent::SlowMotion => scheduler.cycle_slow_motion(),
+                InputEvent::Screenshot => {
+                    let message = take_screenshot(chip, &palette, options, rom_stem, frame);
+                    renderer.notify(&message);
+                }
+                InputEvent::ToggleRecording => {
+                    match recorder.take() {
+                        Some(r) => renderer.notify(&finish_recording(r)),
+                        None => {
+                            let path = format!("{}_frame{}.gif", rom_stem, frame);
+                            match recorder::Recorder::start(&path, &palette, options.record_scale) {
+                                Ok(r) => {
+                                    renderer.notify(&format!("Recording to {}", path));
+                                    recorder = Some(r);
+                                }
+                                Err(e) => renderer.notify(&e),
+                            }
+                        }
+                    }
+                }
+                InputEvent::NextPalette => {
+                    palette = palette.next();
+                    renderer.notify(&format!("Palette: {}", palette.name));
+                    renderer.set_palette(&palette);
+                    redraw = true;
+                }
+                InputEvent::ToggleMute => {
+                    renderer.notify(if beeper.toggle_mute() { "Sound muted" } else { "Sound on" });
+                }
+                InputEvent::Resized(width, height) => {
+                    renderer.resize(width, height);
+                    redraw = true;
+                }
+                InputEvent::Redraw => redraw = true,
+                InputEvent::Renderer(command) => renderer.command(command, chip),
+            }
+        }
+
+        let frames_due = scheduler.frames_due();
+        if frames_due == 0 {
+            beeper.idle();
+        }
+        for _ in 0..frames_due {
+            for _ in 0..scheduler.next_frame() {
+                if !step(chip, debugger, profiler, options, symbols) {
+                    break 'running;
+                }
+            }
+            // The tone sounds for the frames the sound timer is counting down
+            let samples = beeper.frame(chip.get_sound_timer() > 0);
+            if let Some(w) = wav.as_mut() {
+                if let Err(e) = w.add_frame(&samples) {
+                    renderer.notify(&e);
+                    wav = None;
+                }
+            }
+            chip.tick_timers();
+            renderer.end_frame(chip);
+            frame += 1;
+            if options.screenshot_at_frame == Some(frame) {
+                let message = take_screenshot(chip, &palette, options, rom_stem, frame);
+                renderer.notify(&message);
+            }
+            if let Some(r) = recorder.as_mut() {
+                if let Err(e) = r.add_frame(chip) {
+                    renderer.notify(&e);
+                    recorder = None;
+                }
+            }
+        }
+        if step_instruction {
+            step_instruction = false;
+            if !step(chip, debugger, profiler, options, symbols) {
+                break 'running;
+            }
+        }
+
+        let state = scheduler.describe();
+        if state != title_state {
+            renderer.set_title(&if state.is_empty() { "Chip8".to_string() } else { format!("Chip8 - {}", state) });
+            renderer.notify(if state.is_empty() { "running" } else { &state });
+            title_state = state;
+        }
+        let sound = options.sound_indicator && chip.get_sound_timer() > 0;
+        if sound != sound_shown {
+            renderer.set_sound(sound);
+            sound_shown = sound;
+        }
+        input.set_rumble(sound);
+        renderer.update(frame, chip);
+        if chip.take_video_changed() || redraw || renderer.needs_redraw() {
+            renderer.present(chip);
+            redraw = false;
+        }
+        scheduler.wait();
+    }
+
+    if let Some(r) = recorder {
+        renderer.notify(&finish_recording(r));
+    }
+    if let Some(w) = wav {
+        renderer.notify(&finish_wav(w));
+    }
+}
+
+// Named palette from the options with the --fg/--bg overrides applied
+fn load_palette(options: &options::Options) -> palette::Palette {
+    let mut palette = match palette::Palette::named(&options.palette) {
+        Some(p) => p,
+        None => panic!("Unknown palette {}, expected one of {}", options.palette, palette::Palette::NAMES.join(", ")),
+    };
+    let parse = |text: &String| match palette::parse_color(text) {
+        Some(c) => c,
+        None => panic!("Invalid color {}, expected RRGGBB", text),
+    };
+    if let Some(fg) = &options.foreground {
+        palette.set_foreground(parse(fg));
+    }
+    if let Some(bg) = &options.background {
+        palette.set_background(parse(bg));
+    }
+    palette
+}
+
+// Beeper from the options. Without a context or an audio device the sound is
+// only generated, for --wav.
+fn load_beeper(context: Option<&sdl2::Sdl>, options: &options::Options) -> beeper::Beeper {
+    let waveform = match beeper::Waveform::named(&options.waveform) {
+        Some(w) => w,
+        None => panic!("Unknown waveform {}, expected one of {}", options.waveform, beeper::Waveform::NAMES.join(", ")),
+    };
+    let mut beeper = beeper::Beeper::new(options.tone, waveform, options.volume);
+    if options.mute {
+        beeper.toggle_mute();
+    }
+    if let Some(context) = context {
+        if let Err(e) = beeper.play(context) {
+            println!("No audio: {}", e);
+        }
+    }
+    beeper
+}
+
+fn start_wav(options: &options::Options, beeper: &beeper::Beeper) -> Option<wav::WavWriter> {
+    options.wav_path.as_ref().map(|path| match wav::WavWriter::start(path, beeper.get_sample_rate()) {
+        Ok(w) => w,
+        Err(e) => panic!("{}", e),
+    })
+}
+
+// Returns the message to report
+fn finish_wav(wav: wav::WavWriter) -> String {
+    match wav.finish() {
+        Ok((path, frames)) => format!("Wrote {} frames of audio to {}", frames, path),
+        Err(e) => e,
+    }
+}
+
+// Runs without any frontend for a number of frames or until the program halts,
+// then prints the framebuffer and registers
+fn run_headless(
+    chip: &mut chip8::Chip8,
+    options: &options::Options,
+    symbols: &symbols::SymbolMap,
+    debugger: &mut Option<debugger::Debugger>,
+    profiler: &mut Option<profiler::Profiler>,
+    rom_stem: &str,
+) {
+    let mut script = match &options.input_script {
+        Some(path) => match input_script::InputScript::load(path) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        },
+        None => input_script::InputScript::new(),
+    };
+    // Only used for its per-frame instruction counts, nothing waits
+    let mut scheduler = scheduler::Scheduler::new(options.ips);
+    // Sound is only generated, for --wav
+    let mut beeper = load_beeper(None, options);
+    let mut wav = start_wav(options, &beeper);
+    let mut jit = jit_stepper(options);
+    let palette = load_palette(options);
+    let mut recorder = options.record_path.as_ref().map(|path| {
+        match recorder::Recorder::start(path, &palette, options.record_scale) {
+            Ok(r) => r,
+            Err(e) => panic!("{}", e),
+        }
+    });
 
-                Event::KeyUp { keycode, .. } =>
-                    match keycode {
-                        Some(Keycode::X) => chip.set_key(0x0, false),
-                        Some(Keycode::Num1) => chip.set_key(0x1, false),
-                        Some(Keycode::Num2) => chip.set_key(0x2, false),
-                        Some(Keycode::Num3) => chip.set_key(0x3, false),
-                        Some(Keycode::Q) => chip.set_key(0x4, false),
-                        Some(Keycode::W) => chip.set_key(0x5, false),
-                        Some(Keycode::E) => chip.set_key(0x6, false),
-                        Some(Keycode::A) => chip.set_key(0x7, false),
-                        Some(Keycode::S) => chip.set_key(0x8, false),
-                        Some(Keycode::D) => chip.set_key(0x9, false),
-                        Some(Keycode::Z) => chip.set_key(0xA, false),
-                        Some(Keycode::C) => chip.set_key(0xB, false),
-                        Some(Keycode::Num4) => chip.set_key(0xC, false),
-                        Some(Keycode::R) => chip.set_key(0xD, false),
-                        Some(Keycode::F) => chip.set_key(0xE, false),
-                        Some(Keycode::V) => chip.set_key(0xF, false),
-                        _ => {},
+    let mut frame = 0;
+    let mut halted = None;
+    'running: while frame < options.frames {
+        script.apply(frame, chip);
+        let mut remaining = scheduler.next_frame();
+        while remaining > 0 {
+            let pc = chip.get_pc();
+            match jit.as_mut() {
+                Some(run) => remaining -= run(chip, remaining),
+                None => {
+                    if !step(chip, debugger, profiler, options, symbols) {
+                        break 'running;
                     }
-                _ => {}
+                    remaining -= 1;
+                }
+            }
+            if chip.get_pc() == pc {
+                // A jump to itself can never leave, a key wait can't once the script is done
+                let opcode = chip.get_opcode(pc);
+                if opcode == 0x1000 | pc {
+                    halted = Some(format!("jump to itself at {}", symbols.describe(pc)));
+                } else if opcode & 0xF0FF == 0xF00A && script.is_finished() {
+                    halted = Some(format!("waiting for a key at {}", symbols.describe(pc)));
+                }
+                if halted.is_some() {
+                    break 'running;
+                }
+            }
+        }
+        if let Some(w) = wav.as_mut() {
+            if let Err(e) = w.add_frame(&beeper.frame(chip.get_sound_timer() > 0)) {
+                panic!("{}", e);
             }
         }
+        chip.tick_timers();
+        frame += 1;
+        if options.screenshot_at_frame == Some(frame) {
+            println!("{}", take_screenshot(chip, &palette, options, rom_stem, frame));
+        }
+        if let Some(r) = recorder.as_mut() {
+            if let Err(e) = r.add_frame(chip) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    match halted {
+        Some(reason) => println!("Halted in frame {}: {}", frame, reason),
+        None => println!("Stopped after {} frames", frame),
+    }
+    for y in 0..chip8::Chip8::VIDEO_HEIGHT {
+        let line: String = (0..chip8::Chip8::VIDEO_WIDTH)
+            .map(|x| if chip.get_video(x, y) != 0 { '#' } else { '.' })
+            .collect();
+        println!("{}", line);
+    }
+    debugger::print_registers(chip);
+    if let Some(w) = wav {
+        println!("{}", finish_wav(w));
+    }
+    if let Some(r) = recorder {
+        println!("{}", finish_recording(r));
+    }
+
+    if let Some(path) = &options.out_path {
+        match screenshot::render(chip, &palette, options.screenshot_scale).save_png(path) {
+            Ok(()) => println!("Saved framebuffer to {}", path),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+// Returns the message to report
+fn take_screenshot(chip: &chip8::Chip8, palette: &palette::Palette, options: &options::Options, rom_stem: &str, frame: u64) -> String {
+    match screenshot::save(chip, palette, options.screenshot_scale, rom_stem, frame) {
+        Ok(path) => format!("Saved screenshot {}", path),
+        Err(e) => e,
+    }
+}
+
+// Returns the message to report
+fn finish_recording(recorder: recorder::Recorder) -> String {
+    match recorder.finish() {
+        Ok((path, frames)) => format!("Recorded {} frames to {}", frames, path),
+        Err(e) => e,
+    }
+}
+
+// Runs one instruction with the debugger, tracer and profiler hooks. Returns
+// false when the debugger asked to quit.
+fn step(
+    chip: &mut chip8::Chip8,
+    debugger: &mut Option<debugger::Debugger>,
+    profiler: &mut Option<profiler::Profiler>,
+    options: &options::Options,
+    symbols: &symbols::SymbolMap,
+) -> bool {
+    if let Some(debugger) = debugger.as_mut() {
+        if let debugger::DebugAction::Quit = debugger.check(chip, symbols) {
+            return false;
+        }
+    }
+    if options.trace {
+        println!("{}", disasm::disassemble_at(chip, chip.get_pc(), symbols));
+    }
+    if let Some(profiler) = profiler.as_mut() {
+        profiler.record(chip.get_pc());
+    }
+
+    chip.cycle();
+
+    for event in chip.take_smc_events() {
+        if options.smc {
+            println!("{}", debugger::describe_smc(&event, symbols));
+        }
+        if let Some(debugger) = debugger.as_mut() {
+            debugger.notify_smc(&event, symbols);
+        }
+    }
+    true
+}
+
+#[cfg(feature = "jit")]
+fn verify_jit(rom: &Vec<u8>, instructions: u64) {
+    match jit::verify(rom, instructions, 0) {
+        Ok(blocks) => println!("JIT matches the interpreter over {} instructions ({} steps)", instructions, blocks),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn verify_jit(_rom: &Vec<u8>, _instructions: u64) {
+    panic!("Built without the jit feature");
+}
+
+// Runs up to a budget of instructions, returning how many ran
+type Stepper = Box<dyn FnMut(&mut chip8::Chip8, u64) -> u64>;
+
+// The JIT for --jit. Translated blocks skip the per-instruction hooks, so it
+// can't be combined with anything that needs them.
+#[cfg(feature = "jit")]
+fn jit_stepper(options: &options::Options) -> Option<Stepper> {
+    if !options.jit {
+        return None;
+    }
+    if options.debug || !options.breakpoints.is_empty() || options.trace || options.profile || options.smc || options.break_on_smc {
+        panic!("--jit can't be combined with the debugger, --trace, --profile or --smc");
+    }
+    let mut jit = jit::Jit::new();
+    Some(Box::new(move |chip, budget| jit.step(chip, budget)))
+}
 
-        chip.cycle();
-        
-        display.draw(&mut chip);
-        std::thread::sleep(Duration::new(0, 1_000_000_000 / 120));
+#[cfg(not(feature = "jit"))]
+fn jit_stepper(options: &options::Options) -> Option<Stepper> {
+    if options.jit {
+        panic!("Built without the jit feature");
     }
+    None
 }
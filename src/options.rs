@@ -0,0 +1,344 @@
+pub enum Command {
+    Run,
+    // Static analysis of the ROM, no emulation
+    Analyze,
+    // Headless interpreter throughput, of the ROM or of the built-in suite
+    Bench,
+    // Lockstep comparison of the JIT against the interpreter
+    VerifyJit,
+    // Translate the ROM into a Rust module
+    Recompile,
+}
+
+pub enum Frontend {
+    Sdl,
+    // Half-block characters in the terminal
+    Tui,
+}
+
+// Command line options
+pub struct Options {
+    pub command: Command,
+    pub frontend: Frontend,
+    // Run without a window, see --frames and --input
+    pub headless: bool,
+    pub frames: u64,
+    pub input_script: Option<String>,
+    pub rom_path: String,
+    pub symbols_path: Option<String>,
+    pub trace: bool,
+    pub profile: bool,
+    pub debug: bool,
+    // Addresses or labels, resolved once the symbol map is loaded
+    pub breakpoints: Vec<String>,
+    // Memory range for the sprite viewer, "START:END[:HEIGHT]"
+    pub sprites: Option<String>,
+    // Log self-modifying code
+    pub smc: bool,
+    pub break_on_smc: bool,
+    pub decode_cache: bool,
+    // Run --headless through the JIT
+    pub jit: bool,
+    // Instructions per second
+    pub ips: u64,
+    // Initial window size in screen pixels per emulated pixel
+    pub scale: u32,
+    pub stretch: bool,
+    pub fullscreen: bool,
+    // Show the clickable hex keypad next to the game
+    pub keypad: bool,
+    pub palette: String,
+    // Colors overriding the palette, "RRGGBB"
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    // Beeper tone in Hz, waveform name and volume from 0 to 1
+    pub tone: f32,
+    pub waveform: String,
+    pub volume: f32,
+    pub mute: bool,
+    // Show the beeper on screen and rumble controllers while it sounds
+    pub sound_indicator: bool,
+    // Fraction of brightness a pixel loses per frame after turning off
+    pub persistence: Option<f32>,
+    // Frame to save a screenshot after, and screenshot pixels per emulated pixel
+    pub screenshot_at_frame: Option<u64>,
+    pub screenshot_scale: usize,
+    // Recording started at launch, .gif or .y4m
+    pub record_path: Option<String>,
+    pub record_scale: usize,
+    // Beeper sound written frame by frame, in step with --record
+    pub wav_path: Option<String>,
+    pub bench_instructions: u64,
+    pub out_path: Option<String>,
+}
+
+impl Options {
+    const USAGE: &'static str = "Usage: [options] [file_name]
+       analyze [--symbols <file>] [file_name]
+       bench [--instructions <n>] [file_name]
+       verify-jit [--instructions <n>] [file_name]
+       recompile [--symbols <file>] [--out <file>] [file_name]
+Options:
+  --symbols <file>     load a symbol map (<address> <label> [<file>:<line>] per line)
+  --frontend <name>    sdl (default) or tui to draw in the terminal, not with --headless
+  --headless           run without a window, then print the framebuffer and registers
+  --frames <n>         frames to run headless (default 600), stops early on a halt
+  --input <file>       scripted keypad input for --headless, '<frame> down|up <key>' per line
+  --trace              print every executed instruction
+  --profile            print an execution profile on exit
+  --debug              start in the debugger
+  --break <address>    stop in the debugger at an address or label (repeatable)
+  --sprites <range>    open the sprite viewer on START:END[:HEIGHT]
+  --smc                log self-modifying code
+  --break-on-smc       stop in the debugger on self-modifying code
+  --decode-cache       cache decoded instructions
+  --jit                run --headless through the JIT (needs the jit feature)
+  --ips <n>            instructions per second (default 700)
+  --scale <n>          initial window scale (default 24)
+  --stretch            fill the window instead of scaling by whole multiples
+  --fullscreen         start in fullscreen
+  --keypad             show a clickable keypad that lights up keys the program reads
+  --palette <name>     classic, amber, green, lcd or high-contrast
+  --fg <RRGGBB>        foreground color, overrides the palette
+  --bg <RRGGBB>        background color, overrides the palette
+  --tone <hz>          beeper frequency (default 440)
+  --waveform <name>    square (default), sine or triangle
+  --volume <f>         beeper volume from 0 to 1 (default 0.25)
+  --mute               start with the beeper muted, with --frontend tui no audio
+                       device is opened at all
+  --sound-indicator    flash a border and rumble controllers while the beeper sounds
+  --persistence <f>    fade pixels out, losing f (0 to 1) of their brightness per frame
+  --screenshot-at-frame <n>
+                       save a PNG screenshot after frame n, also with --headless
+  --screenshot-scale <n>
+                       screenshot pixels per emulated pixel (default 8)
+  --record <file>      record every frame to an animated .gif or a raw .y4m video,
+                       also with --headless
+  --record-scale <n>   recording pixels per emulated pixel (default 4)
+  --wav <file>         write the beeper's sound to a WAV file, also with --headless,
+                       unaffected by muting
+  --instructions <n>   instructions per benchmark run (default 10000000)
+                       bench without a file runs the built-in suite
+  --out <file>         output file for recompile (default stdout), or the final
+                       framebuffer PNG for --headless";
+
+    pub fn parse(args: &[String]) -> Options {
+        let mut options = Options {
+            command: Command::Run,
+            frontend: Frontend::Sdl,
+            headless: false,
+            frames: 600,
+            input_script: None,
+            rom_path: String::new(),
+            symbols_path: None,
+            trace: false,
+            profile: false,
+            debug: false,
+            breakpoints: Vec::new(),
+            sprites: None,
+            smc: false,
+            break_on_smc: false,
+            decode_cache: false,
+            jit: false,
+            ips: 700,
+            scale: 24,
+            stretch: false,
+            fullscreen: false,
+            keypad: false,
+            palette: "classic".to_string(),
+            foreground: None,
+            background: None,
+            tone: 440.0,
+            waveform: "square".to_string(),
+            volume: 0.25,
+            mute: false,
+            sound_indicator: false,
+            persistence: None,
+            screenshot_at_frame: None,
+            screenshot_scale: 8,
+            record_path: None,
+            record_scale: 4,
+            wav_path: None,
+            bench_instructions: 10_000_000,
+            out_path: None,
+        };
+
+        let mut rom_path = None;
+        // A frontend makes no sense with --headless, so remember whether one was given
+        let mut frontend_given = false;
+        let mut i = 1;
+        match args.get(1).map(|a| a.as_str()) {
+            Some("analyze") => options.command = Command::Analyze,
+            Some("bench") => options.command = Command::Bench,
+            Some("verify-jit") => options.command = Command::VerifyJit,
+            Some("recompile") => options.command = Command::Recompile,
+            _ => i -= 1,
+        }
+        i += 1;
+
+        while i < args.len() {
+            match args[i].as_str() {
+                "--symbols" => options.symbols_path = Some(Options::value(args, &mut i)),
+                "--frontend" => {
+                    options.frontend = match Options::value(args, &mut i).as_str() {
+                        "sdl" => Frontend::Sdl,
+                        "tui" => Frontend::Tui,
+                        other => panic!("Unknown frontend {}, expected sdl or tui.\n{}", other, Options::USAGE),
+                    };
+                    frontend_given = true;
+                }
+                "--headless" => options.headless = true,
+                "--frames" => options.frames = Options::number(args, &mut i),
+                "--input" => options.input_script = Some(Options::value(args, &mut i)),
+                "--trace" => options.trace = true,
+                "--profile" => options.profile = true,
+                "--debug" => options.debug = true,
+                "--break" => options.breakpoints.push(Options::value(args, &mut i)),
+                "--sprites" => options.sprites = Some(Options::value(args, &mut i)),
+                "--smc" => options.smc = true,
+                "--break-on-smc" => options.break_on_smc = true,
+                "--decode-cache" => options.decode_cache = true,
+                "--jit" => options.jit = true,
+                "--ips" => options.ips = Options::number(args, &mut i),
+                "--scale" => options.scale = Options::at_least_one(args, &mut i),
+                "--stretch" => options.stretch = true,
+                "--fullscreen" => options.fullscreen = true,
+                "--keypad" => options.keypad = true,
+                "--palette" => options.palette = Options::value(args, &mut i),
+                "--fg" => options.foreground = Some(Options::value(args, &mut i)),
+                "--bg" => options.background = Some(Options::value(args, &mut i)),
+                "--tone" => options.tone = Options::checked_number(args, &mut i, |hz: &f32| hz.is_finite() && *hz > 0.0, "a positive frequency"),
+                "--waveform" => options.waveform = Options::value(args, &mut i),
+                "--volume" => options.volume = Options::checked_number(args, &mut i, |v| (0.0..=1.0).contains(v), "0 to 1"),
+                "--mute" => options.mute = true,
+                "--sound-indicator" => options.sound_indicator = true,
+                "--persistence" => options.persistence = Some(Options::number(args, &mut i)),
+                "--screenshot-at-frame" => options.screenshot_at_frame = Some(Options::number(args, &mut i)),
+                "--screenshot-scale" => options.screenshot_scale = Options::at_least_one(args, &mut i),
+                "--record" => options.record_path = Some(Options::value(args, &mut i)),
+                "--record-scale" => options.record_scale = Options::at_least_one(args, &mut i),
+                "--wav" => options.wav_path = Some(Options::value(args, &mut i)),
+                "--instructions" => options.bench_instructions = Options::number(args, &mut i),
+                "--out" => options.out_path = Some(Options::value(args, &mut i)),
+                arg if arg.starts_with("--") || rom_path.is_some() => {
+                    panic!("Unexpected argument {}.\n{}", arg, Options::USAGE)
+                }
+                arg => rom_path = Some(arg.to_string()),
+            }
+            i += 1;
+        }
+
+        if options.headless && frontend_given {
+            panic!("--frontend can't be used with --headless.\n{}", Options::USAGE);
+        }
+
+        options.rom_path = match rom_path {
+            Some(p) => p,
+            // Without a ROM, bench runs its built-in suite
+            None if matches!(options.command, Command::Bench) => String::new(),
+            None => panic!("{}\nGot {:?}", Options::USAGE, args),
+        };
+        options
+    }
+
+    fn number<T: std::str::FromStr>(args: &[String], i: &mut usize) -> T {
+        let value = Options::value(args, i);
+        match value.parse::<T>() {
+            Ok(n) => n,
+            Err(_) => panic!("Invalid number {} for {}.\n{}", value, args[*i - 1], Options::USAGE),
+        }
+    }
+
+    // Number that must be 1 or more, like a scale
+    fn at_least_one<T: std::str::FromStr + PartialOrd + From<u8>>(args: &[String], i: &mut usize) -> T {
+        Options::checked_number(args, i, |n| *n >= T::from(1), "at least 1")
+    }
+
+    // Number that must pass valid, described by expected otherwise
+    fn checked_number<T: std::str::FromStr>(args: &[String], i: &mut usize, valid: impl Fn(&T) -> bool, expected: &str) -> T {
+        let n = Options::number(args, i);
+        if !valid(&n) {
+            panic!("Invalid number {} for {}, expected {}.\n{}", args[*i], args[*i - 1], expected, Options::USAGE);
+        }
+        n
+    }
+
+    // Value following the option at args[*i]
+    fn value(args: &[String], i: &mut usize) -> String {
+        *i += 1;
+        match args.get(*i) {
+            Some(v) => v.clone(),
+            None => panic!("Missing value for {}.\n{}", args[*i - 1], Options::USAGE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        let args: Vec<String> = ["chip8"].iter().chain(args).map(|a| a.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    #[should_panic(expected = "--frontend can't be used with --headless")]
+    fn rejects_a_frontend_when_headless() {
+        parse(&["rom.ch8", "--headless", "--frontend", "tui"]);
+    }
+
+    #[test]
+    fn records_and_screenshots_when_headless() {
+        let options = parse(&["--headless", "rom.ch8", "--record", "out.gif", "--screenshot-at-frame", "10"]);
+        assert!(options.headless);
+        assert_eq!(options.record_path.as_deref(), Some("out.gif"));
+        assert_eq!(options.screenshot_at_frame, Some(10));
+    }
+
+    #[test]
+    fn accepts_tones_and_volumes_in_range() {
+        let options = parse(&["rom.ch8", "--tone", "0.5", "--volume", "0"]);
+        assert_eq!((options.tone, options.volume), (0.5, 0.0));
+        assert_eq!(parse(&["rom.ch8", "--volume", "1"]).volume, 1.0);
+    }
+
+    #[test]
+    fn rejects_tones_that_are_not_positive() {
+        for tone in ["0", "-440", "NaN", "inf"] {
+            let result = std::panic::catch_unwind(|| parse(&["rom.ch8", "--tone", tone]));
+            assert!(result.is_err(), "{}", tone);
+        }
+    }
+
+    #[test]
+    fn rejects_volumes_outside_zero_to_one() {
+        for volume in ["-0.1", "1.5", "NaN"] {
+            let result = std::panic::catch_unwind(|| parse(&["rom.ch8", "--volume", volume]));
+            assert!(result.is_err(), "{}", volume);
+        }
+    }
+
+    #[test]
+    fn accepts_a_scale_of_one() {
+        assert_eq!(parse(&["rom.ch8", "--scale", "1"]).scale, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected at least 1")]
+    fn rejects_a_zero_scale() {
+        parse(&["rom.ch8", "--scale", "0"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected at least 1")]
+    fn rejects_a_zero_screenshot_scale() {
+        parse(&["rom.ch8", "--screenshot-scale", "0"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected at least 1")]
+    fn rejects_a_zero_record_scale() {
+        parse(&["rom.ch8", "--record-scale", "0"]);
+    }
+}
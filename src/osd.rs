@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+
+use crate::chip8::Chip8;
+
+// Text shown over the game by Chip8Display: a transient notification, frame
+// and instruction rates, and a register readout. Only the text is decided
+// here; the display lays it out with the bitmap font.
+pub struct Osd {
+    message: Option<(String, Instant)>,
+    show_stats: bool,
+    show_registers: bool,
+
+    // Rates over the last full second
+    fps: u64,
+    ips: u64,
+    sample_start: Instant,
+    sample_frame: u64,
+    sample_cycles: u64,
+
+    // Shown text changed since the last draw
+    dirty: bool,
+}
+
+impl Osd {
+    const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+    const SAMPLE_PERIOD: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Osd {
+        Osd {
+            message: None,
+            show_stats: false,
+            show_registers: false,
+            fps: 0,
+            ips: 0,
+            sample_start: Instant::now(),
+            sample_frame: 0,
+            sample_cycles: 0,
+            dirty: false,
+        }
+    }
+
+    // Shows text for a couple of seconds, replacing any earlier message
+    pub fn notify(&mut self, text: &str) {
+        self.message = Some((text.to_string(), Instant::now()));
+        self.dirty = true;
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+        self.dirty = true;
+    }
+
+    pub fn toggle_registers(&mut self) {
+        self.show_registers = !self.show_registers;
+        self.dirty = true;
+    }
+
+    // Called every loop with the emulated frame count, updates rates and expires the message
+    pub fn update(&mut self, frame: u64, chip8: &Chip8) {
+        let elapsed = self.sample_start.elapsed();
+        if elapsed >= Osd::SAMPLE_PERIOD {
+            let seconds = elapsed.as_secs_f64();
+            self.fps = ((frame - self.sample_frame) as f64 / seconds).round() as u64;
+            self.ips = ((chip8.get_cycles() - self.sample_cycles) as f64 / seconds).round() as u64;
+            self.sample_start = Instant::now();
+            self.sample_frame = frame;
+            self.sample_cycles = chip8.get_cycles();
+            self.dirty |= self.show_stats;
+        }
+
+        if let Some((_, shown_at)) = &self.message {
+            if shown_at.elapsed() >= Osd::MESSAGE_DURATION {
+                self.message = None;
+                self.dirty = true;
+            }
+        }
+    }
+
+    // Whether the display must redraw even though the video didn't change
+    pub fn needs_redraw(&self) -> bool {
+        // Registers change with almost every instruction
+        self.dirty || self.show_registers
+    }
+
+    // Lines for the top left corner
+    pub fn status_lines(&self, chip8: &Chip8) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.show_stats {
+            lines.push(format!("{} FPS  {} IPS", self.fps, self.ips));
+        }
+        if self.show_registers {
+            let registers = chip8.get_registers();
+            for row in 0..4 {
+                let line: Vec<String> = (0..4)
+                    .map(|i| format!("V{:X}={:02X}", row * 4 + i, registers[row * 4 + i]))
+                    .collect();
+                lines.push(line.join(" "));
+            }
+            lines.push(format!("I={:03X} PC={:03X}", chip8.get_index_register(), chip8.get_pc()));
+            lines.push(format!("DT={:02X} ST={:02X}", chip8.get_delay_timer(), chip8.get_sound_timer()));
+        }
+        lines
+    }
+
+    // Notification for the bottom left corner
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(|(text, _)| text.as_str())
+    }
+
+    pub fn mark_drawn(&mut self) {
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_notifications() {
+        let chip = Chip8::new(&vec![]);
+        let mut osd = Osd::new();
+        osd.notify("Saved");
+        osd.update(0, &chip);
+        assert_eq!(osd.message(), Some("Saved"));
+
+        osd.mark_drawn();
+        osd.message = Some(("Saved".to_string(), Instant::now().checked_sub(Osd::MESSAGE_DURATION).unwrap()));
+        osd.update(0, &chip);
+        assert_eq!(osd.message(), None);
+        assert!(osd.needs_redraw());
+    }
+
+    #[test]
+    fn redraws_only_after_changes_or_with_registers_shown() {
+        let chip = Chip8::new(&vec![]);
+        let mut osd = Osd::new();
+        assert!(!osd.needs_redraw());
+        osd.notify("Hello");
+        assert!(osd.needs_redraw());
+        osd.mark_drawn();
+        assert!(!osd.needs_redraw());
+
+        // New rates only matter when they are shown
+        osd.sample_start = Instant::now().checked_sub(Osd::SAMPLE_PERIOD).unwrap();
+        osd.update(60, &chip);
+        assert!(!osd.needs_redraw());
+        osd.toggle_stats();
+        osd.mark_drawn();
+        osd.sample_start = Instant::now().checked_sub(Osd::SAMPLE_PERIOD).unwrap();
+        osd.update(120, &chip);
+        assert!(osd.needs_redraw());
+
+        osd.mark_drawn();
+        osd.toggle_registers();
+        osd.mark_drawn();
+        assert!(osd.needs_redraw());
+    }
+
+    #[test]
+    fn lists_stats_then_registers() {
+        let mut chip = Chip8::new(&vec![]);
+        chip.op_6XNN(0x5, 0xAB);
+        chip.op_ANNN(0x123);
+        let mut osd = Osd::new();
+        assert!(osd.status_lines(&chip).is_empty());
+
+        osd.toggle_stats();
+        osd.fps = 60;
+        osd.ips = 700;
+        assert_eq!(osd.status_lines(&chip), vec!["60 FPS  700 IPS"]);
+
+        osd.toggle_registers();
+        let lines = osd.status_lines(&chip);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[1], "V0=00 V1=00 V2=00 V3=00");
+        assert_eq!(lines[2], "V4=00 V5=AB V6=00 V7=00");
+        assert_eq!(lines[5], "I=123 PC=200");
+        assert_eq!(lines[6], "DT=00 ST=00");
+    }
+}
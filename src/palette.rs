@@ -0,0 +1,108 @@
+// Colors for each pixel value. Index 0 is the background and 1 the
+// foreground; 2 and 3 are reserved for the XO-CHIP plane combinations.
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [(u8, u8, u8); 4],
+}
+
+impl Palette {
+    // In the order the palette hotkey cycles through them
+    pub const NAMES: [&'static str; 5] = ["classic", "amber", "green", "lcd", "high-contrast"];
+
+    pub fn named(name: &str) -> Option<Palette> {
+        let colors = match name {
+            "classic" => [(0x00, 0x00, 0x00), (0xFF, 0xFF, 0xFF), (0xAA, 0xAA, 0xAA), (0x55, 0x55, 0x55)],
+            "amber" => [(0x1A, 0x0F, 0x00), (0xFF, 0xB0, 0x00), (0xCC, 0x7A, 0x00), (0x66, 0x3D, 0x00)],
+            "green" => [(0x00, 0x14, 0x00), (0x33, 0xFF, 0x33), (0x1F, 0xAA, 0x1F), (0x0F, 0x55, 0x0F)],
+            "lcd" => [(0x9B, 0xBC, 0x0F), (0x0F, 0x38, 0x0F), (0x30, 0x62, 0x30), (0x8B, 0xAC, 0x0F)],
+            "high-contrast" => [(0x00, 0x00, 0x00), (0xFF, 0xFF, 0x00), (0x00, 0xFF, 0xFF), (0xFF, 0x00, 0xFF)],
+            _ => return None,
+        };
+        Some(Palette {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    // Next named palette, wrapping around. Custom palettes go back to the first one.
+    pub fn next(&self) -> Palette {
+        let index = Palette::NAMES.iter().position(|n| *n == self.name).map_or(0, |i| i + 1);
+        Palette::named(Palette::NAMES[index % Palette::NAMES.len()]).unwrap()
+    }
+
+    pub fn set_background(&mut self, color: (u8, u8, u8)) {
+        self.colors[0] = color;
+        self.name = "custom".to_string();
+    }
+
+    pub fn set_foreground(&mut self, color: (u8, u8, u8)) {
+        self.colors[1] = color;
+        self.name = "custom".to_string();
+    }
+
+    pub fn color(&self, value: u32) -> (u8, u8, u8) {
+        self.colors[value as usize & 3]
+    }
+}
+
+// "RRGGBB" or "#RRGGBB"
+pub fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_every_palette() {
+        for name in Palette::NAMES.iter() {
+            assert_eq!(Palette::named(name).unwrap().name, *name);
+        }
+        assert!(Palette::named("custom").is_none());
+        assert!(Palette::named("").is_none());
+    }
+
+    #[test]
+    fn cycles_through_the_named_palettes() {
+        let mut palette = Palette::named("classic").unwrap();
+        for name in Palette::NAMES.iter().skip(1).chain(["classic"].iter()) {
+            palette = palette.next();
+            assert_eq!(palette.name, *name);
+        }
+
+        palette.set_foreground((1, 2, 3));
+        assert_eq!(palette.name, "custom");
+        assert_eq!(palette.next().name, "classic");
+    }
+
+    #[test]
+    fn overrides_colors_and_masks_pixel_values() {
+        let mut palette = Palette::named("amber").unwrap();
+        palette.set_background((1, 2, 3));
+        palette.set_foreground((4, 5, 6));
+        assert_eq!(palette.color(0), (1, 2, 3));
+        assert_eq!(palette.color(1), (4, 5, 6));
+        assert_eq!(palette.color(5), (4, 5, 6));
+        assert_eq!(palette.color(2), (0xCC, 0x7A, 0x00));
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_color("FFB000"), Some((0xFF, 0xB0, 0x00)));
+        assert_eq!(parse_color("#0f380f"), Some((0x0F, 0x38, 0x0F)));
+        assert_eq!(parse_color("#FFF"), None);
+        assert_eq!(parse_color("FFB0000"), None);
+        assert_eq!(parse_color("+FB000"), None);
+        assert_eq!(parse_color("GGGGGG"), None);
+        assert_eq!(parse_color("##FFB000"), None);
+        // Six bytes but three characters
+        assert_eq!(parse_color("ÿÿÿ"), None);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::chip8::Chip8;
+
+// Phosphor persistence: a pixel that turns off fades out over several frames
+// instead of going dark at once, which hides the flicker of XOR redraws.
+pub struct Persistence {
+    // Fraction of the brightness lost per frame, in (0, 1]
+    decay: f32,
+    levels: [f32; Chip8::VIDEO_WIDTH * Chip8::VIDEO_HEIGHT],
+}
+
+impl Persistence {
+    // Below this a pixel is treated as fully off
+    const CUTOFF: f32 = 1.0 / 256.0;
+
+    pub fn new(decay: f32) -> Persistence {
+        Persistence {
+            decay: decay.clamp(Persistence::CUTOFF, 1.0),
+            levels: [0.0; Chip8::VIDEO_WIDTH * Chip8::VIDEO_HEIGHT],
+        }
+    }
+
+    // Advances one frame: lit pixels go to full brightness, the rest decay
+    pub fn update(&mut self, chip8: &Chip8) {
+        for y in 0..Chip8::VIDEO_HEIGHT {
+            let row = chip8.get_video_row(y);
+            for x in 0..Chip8::VIDEO_WIDTH {
+                let level = &mut self.levels[y * Chip8::VIDEO_WIDTH + x];
+                if (row >> (Chip8::VIDEO_WIDTH - 1 - x)) & 1 != 0 {
+                    *level = 1.0;
+                } else if *level > Persistence::CUTOFF {
+                    *level *= 1.0 - self.decay;
+                } else {
+                    *level = 0.0;
+                }
+            }
+        }
+    }
+
+    // Brightness of a pixel from 0 (off) to 1 (lit)
+    pub fn level(&self, x: usize, y: usize) -> f32 {
+        self.levels[y * Chip8::VIDEO_WIDTH + x]
+    }
+
+    // Whether some pixel is still fading, so frames change without new video
+    pub fn is_fading(&self) -> bool {
+        self.levels.iter().any(|l| *l > 0.0 && *l < 1.0)
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use crate::symbols::SymbolMap;
+
+// Counts how many times each address is executed
+pub struct Profiler {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Profiler {
+    const TOP_ENTRIES: usize = 20;
+
+    pub fn new() -> Profiler {
+        Profiler {
+            counts: vec![0; 4096],
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, pc: u16) {
+        let len = self.counts.len();
+        self.counts[pc as usize % len] += 1;
+        self.total += 1;
+    }
+
+    pub fn report(&self, symbols: &SymbolMap) -> String {
+        let mut out = format!("Profile: {} instructions executed\n", self.total);
+        if self.total == 0 {
+            return out;
+        }
+
+        let mut addresses: Vec<(u16, u64)> = self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .map(|(a, c)| (a as u16, *c))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        out.push_str("\nHottest addresses:\n");
+        for (address, count) in addresses.iter().take(Profiler::TOP_ENTRIES) {
+            out.push_str(&self.format_entry(&symbols.describe(*address), *count));
+        }
+
+        // Attribute each address to the nearest preceding label
+        let mut by_label: HashMap<&str, u64> = HashMap::new();
+        for (address, count) in &addresses {
+            let label = symbols.nearest_label(*address).map(|(l, _)| l).unwrap_or("<unlabeled>");
+            *by_label.entry(label).or_insert(0) += count;
+        }
+
+        if by_label.len() > 1 || !by_label.contains_key("<unlabeled>") {
+            let mut labels: Vec<(&str, u64)> = by_label.into_iter().collect();
+            labels.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+            out.push_str("\nBy symbol:\n");
+            for (label, count) in labels.iter().take(Profiler::TOP_ENTRIES) {
+                out.push_str(&self.format_entry(label, *count));
+            }
+        }
+
+        out
+    }
+
+    fn format_entry(&self, name: &str, count: u64) -> String {
+        let percent = count as f64 * 100.0 / self.total as f64;
+        format!("  {:<32}{:>12}  {:>6.2}%\n", name, count, percent)
+    }
+}
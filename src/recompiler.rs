@@ -0,0 +1,359 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::analyzer::Analysis;
+use crate::disasm;
+use crate::instruction::Instruction;
+use crate::symbols::SymbolMap;
+
+// Translates the reachable code of a ROM into a Rust module with one function
+// per basic block. The module drives a Chip8 through its public API, so the
+// framebuffer, timers and keys behave exactly as under the interpreter.
+pub struct Recompiler<'a> {
+    rom: &'a [u8],
+    analysis: Analysis,
+    // Block boundaries from control flow. Guessed BNNN targets get blocks of
+    // their own but do not split the blocks running through them.
+    boundaries: BTreeSet<u16>,
+    // Bytes a store may write to, never compiled
+    modified: BTreeSet<u16>,
+    // Some store has an address not known at compile time, so every block
+    // checks its code is unchanged before running and ends after any such
+    // store, which may have overwritten the rest of it
+    guard_blocks: bool,
+}
+
+impl<'a> Recompiler<'a> {
+    const START_ADDRESS: u16 = 0x200;
+    // Candidate targets followed from each BNNN, i.e. every even V0
+    const JUMP_TABLE_ENTRIES: u16 = 128;
+
+    pub fn new(rom: &'a [u8]) -> Recompiler<'a> {
+        let rom_end = Recompiler::START_ADDRESS as usize + rom.len();
+
+        // BNNN targets are only known at run time. Treat the range they can reach
+        // as a jump table so the likely targets get compiled as well.
+        let first_pass = Analysis::run(rom);
+        let mut entries = vec![Recompiler::START_ADDRESS];
+        for address in first_pass.indirect_jumps() {
+            let base = read_opcode(rom, *address) & 0xFFF;
+            for i in 0..Recompiler::JUMP_TABLE_ENTRIES {
+                let target = base + i * 2;
+                if target >= Recompiler::START_ADDRESS && (target as usize) + 1 < rom_end {
+                    entries.push(target);
+                }
+            }
+        }
+
+        let analysis = Analysis::run_from(rom, &entries);
+        let guessed: BTreeSet<u16> = entries.into_iter()
+            .filter(|e| !first_pass.leaders().contains(e))
+            .collect();
+        let boundaries = analysis.leaders().difference(&guessed).cloned().collect();
+
+        let mut recompiler = Recompiler {
+            rom,
+            analysis,
+            boundaries,
+            modified: BTreeSet::new(),
+            guard_blocks: false,
+        };
+        recompiler.find_stores();
+        recompiler
+    }
+
+    // Tracks I through each block to find what FX33/FX55 can write to. Blocks
+    // at guessed BNNN targets are skipped, the block running through each
+    // target sees the same stores with more of I's history.
+    fn find_stores(&mut self) {
+        let mut unknown_store = false;
+        for leader in self.boundaries.clone() {
+            let mut index: Option<u16> = None;
+            for (_, opcode) in self.block_instructions(leader) {
+                let length = match Instruction::decode(opcode) {
+                    Instruction::OpANNN(nnn) => {
+                        index = Some(nnn);
+                        continue;
+                    }
+                    Instruction::OpFX1E(_) | Instruction::OpFX29(_) => {
+                        index = None;
+                        continue;
+                    }
+                    Instruction::OpFX33(_) => 3,
+                    Instruction::OpFX55(x) => x as u16 + 1,
+                    _ => continue,
+                };
+
+                match index {
+                    Some(start) => self.modified.extend(start..start + length),
+                    None => unknown_store = true,
+                }
+            }
+        }
+        // Set after the loop so every block above was scanned in full
+        self.guard_blocks = unknown_store;
+    }
+
+    // Instructions of the block starting at leader, up to and including its
+    // terminator. Stops before leaving the ROM, unreachable code, a block
+    // boundary, bytes a store writes to, and opcodes the core does not handle.
+    // Guarded blocks also stop after a store through an I not set in the block.
+    fn block_instructions(&self, leader: u16) -> Vec<(u16, u16)> {
+        let rom_end = Recompiler::START_ADDRESS as usize + self.rom.len();
+        let mut instructions = Vec::new();
+        let mut address = leader;
+        let mut index_known = false;
+
+        loop {
+            if (address as usize) + 1 >= rom_end
+                || !self.analysis.is_reachable(address)
+                || self.modified.contains(&address)
+                || self.modified.contains(&(address + 1))
+                || (address != leader && self.boundaries.contains(&address)) {
+                break;
+            }
+
+            let opcode = read_opcode(self.rom, address);
+            let instruction = Instruction::decode(opcode);
+            if let Instruction::Invalid(_) = instruction {
+                break;
+            }
+
+            let unknown_store = match instruction {
+                Instruction::OpANNN(_) => {
+                    index_known = true;
+                    false
+                }
+                Instruction::OpFX1E(_) | Instruction::OpFX29(_) => {
+                    index_known = false;
+                    false
+                }
+                Instruction::OpFX33(_) | Instruction::OpFX55(_) => !index_known,
+                _ => false,
+            };
+
+            instructions.push((address, opcode));
+            if ends_block(instruction) || (self.guard_blocks && unknown_store) {
+                break;
+            }
+            address += 2;
+        }
+
+        instructions
+    }
+
+    // Addresses to compile a block at: the analysis' leaders, plus the address
+    // after each block that stops without a terminator, e.g. after a store to
+    // an unknown address. Those that can't start a block get no instructions.
+    fn block_leaders(&self) -> BTreeSet<u16> {
+        let mut leaders = self.analysis.leaders().clone();
+        let mut pending: Vec<u16> = leaders.iter().cloned().collect();
+        while let Some(leader) = pending.pop() {
+            if let Some((address, opcode)) = self.block_instructions(leader).last() {
+                if !ends_block(Instruction::decode(*opcode)) && leaders.insert(address + 2) {
+                    pending.push(address + 2);
+                }
+            }
+        }
+        leaders
+    }
+
+    pub fn generate(&self, rom_name: &str, symbols: &SymbolMap) -> String {
+        let blocks: Vec<(u16, Vec<(u16, u16)>)> = self.block_leaders()
+            .iter()
+            .map(|leader| (*leader, self.block_instructions(*leader)))
+            .filter(|(_, instructions)| !instructions.is_empty())
+            .collect();
+
+        let mut out = String::new();
+        writeln!(out, "// Generated by `chip-8-emu-rust recompile` from {}. Do not edit.", rom_name).unwrap();
+        writeln!(out, "//").unwrap();
+        writeln!(out, "// Add this file as a module of the emulator and call `run` in place of").unwrap();
+        writeln!(out, "// Chip8::cycle. Each basic block is a function over the Chip8 API; any").unwrap();
+        writeln!(out, "// other address is interpreted.").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "use crate::chip8::Chip8;").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(out, "pub const ROM: [u8; {}] = [", self.rom.len()).unwrap();
+        for chunk in self.rom.chunks(12) {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+            writeln!(out, "    {},", bytes.join(", ")).unwrap();
+        }
+        writeln!(out, "];").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(out, "pub fn new_chip() -> Chip8 {{").unwrap();
+        writeln!(out, "    Chip8::new(&ROM.to_vec())").unwrap();
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(out, "// Runs at least budget instructions, finishing the block in progress.").unwrap();
+        writeln!(out, "// Returns the instructions executed.").unwrap();
+        writeln!(out, "pub fn run(chip: &mut Chip8, budget: u64) -> u64 {{").unwrap();
+        writeln!(out, "    let mut executed = 0;").unwrap();
+        writeln!(out, "    while executed < budget {{").unwrap();
+        writeln!(out, "        executed += dispatch(chip);").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "    executed").unwrap();
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(out, "// Maps the PC to its block, which also resolves BNNN jumps at run time").unwrap();
+        writeln!(out, "fn dispatch(chip: &mut Chip8) -> u64 {{").unwrap();
+        writeln!(out, "    match chip.get_pc() {{").unwrap();
+        for (leader, instructions) in &blocks {
+            let end = instructions.last().unwrap().0 + 2;
+            if self.guard_blocks {
+                writeln!(out, "        0x{:03X} if intact(chip, 0x{:03X}, 0x{:03X}) => block_{:03x}(chip),",
+                    leader, leader, end, leader).unwrap();
+            } else {
+                writeln!(out, "        0x{:03X} => block_{:03x}(chip),", leader, leader).unwrap();
+            }
+        }
+        writeln!(out, "        _ => {{").unwrap();
+        writeln!(out, "            chip.cycle();").unwrap();
+        writeln!(out, "            1").unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+
+        if self.guard_blocks {
+            writeln!(out).unwrap();
+            writeln!(out, "// Whether memory in [start, end) still holds the ROM's code").unwrap();
+            writeln!(out, "fn intact(chip: &Chip8, start: u16, end: u16) -> bool {{").unwrap();
+            writeln!(out, "    (start..end).all(|a| chip.get_memory(a) == ROM[(a - 0x{:03X}) as usize])",
+                Recompiler::START_ADDRESS).unwrap();
+            writeln!(out, "}}").unwrap();
+        }
+
+        for (leader, instructions) in &blocks {
+            writeln!(out).unwrap();
+            self.generate_block(&mut out, *leader, instructions, symbols);
+        }
+
+        out
+    }
+
+    fn generate_block(&self, out: &mut String, leader: u16, instructions: &[(u16, u16)], symbols: &SymbolMap) {
+        writeln!(out, "// {}", symbols.describe(leader)).unwrap();
+        writeln!(out, "fn block_{:03x}(chip: &mut Chip8) -> u64 {{", leader).unwrap();
+
+        for (address, opcode) in instructions {
+            let instruction = Instruction::decode(*opcode);
+            writeln!(out, "    // {:03X}  {}", address, disasm::disassemble(*opcode, Some(symbols))).unwrap();
+
+            if ends_block(instruction) {
+                writeln!(out, "    chip.set_pc(0x{:03X});", address + 2).unwrap();
+            }
+            writeln!(out, "    chip.{};", call(instruction)).unwrap();
+        }
+
+        let (last, last_opcode) = *instructions.last().unwrap();
+        if !ends_block(Instruction::decode(last_opcode)) {
+            writeln!(out, "    chip.set_pc(0x{:03X});", last + 2).unwrap();
+        }
+        writeln!(out, "    chip.retire({});", instructions.len()).unwrap();
+        writeln!(out, "    {}", instructions.len()).unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+}
+
+// Instructions that read or set the PC end a block
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(instruction,
+        Instruction::Op00EE | Instruction::Op1NNN(_) | Instruction::Op2NNN(_)
+        | Instruction::Op3XNN(..) | Instruction::Op4XNN(..) | Instruction::Op5XY0(..)
+        | Instruction::Op9XY0(..) | Instruction::OpBNNN(_) | Instruction::OpEX9E(_)
+        | Instruction::OpEXA1(_) | Instruction::OpFX0A(_))
+}
+
+// Chip8 method call executing the instruction
+fn call(instruction: Instruction) -> String {
+    match instruction {
+        Instruction::Op00E0 => "op_00E0()".to_string(),
+        Instruction::Op00EE => "op_00EE()".to_string(),
+        Instruction::Op1NNN(nnn) => format!("op_1NNN(0x{:03X})", nnn),
+        Instruction::Op2NNN(nnn) => format!("op_2NNN(0x{:03X})", nnn),
+        Instruction::Op3XNN(x, nn) => format!("op_3XNN(0x{:X}, 0x{:02X})", x, nn),
+        Instruction::Op4XNN(x, nn) => format!("op_4XNN(0x{:X}, 0x{:02X})", x, nn),
+        Instruction::Op5XY0(x, y) => format!("op_5XY0(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op6XNN(x, nn) => format!("op_6XNN(0x{:X}, 0x{:02X})", x, nn),
+        Instruction::Op7XNN(x, nn) => format!("op_7XNN(0x{:X}, 0x{:02X})", x, nn),
+        Instruction::Op8XY0(x, y) => format!("op_8XY0(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY1(x, y) => format!("op_8XY1(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY2(x, y) => format!("op_8XY2(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY3(x, y) => format!("op_8XY3(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY4(x, y) => format!("op_8XY4(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY5(x, y) => format!("op_8XY5(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XY6(x) => format!("op_8XY6(0x{:X})", x),
+        Instruction::Op8XY7(x, y) => format!("op_8XY7(0x{:X}, 0x{:X})", x, y),
+        Instruction::Op8XYE(x) => format!("op_8XYE(0x{:X})", x),
+        Instruction::Op9XY0(x, y) => format!("op_9XY0(0x{:X}, 0x{:X})", x, y),
+        Instruction::OpANNN(nnn) => format!("op_ANNN(0x{:03X})", nnn),
+        Instruction::OpBNNN(nnn) => format!("op_BNNN(0x{:03X})", nnn),
+        Instruction::OpCXNN(x, nn) => format!("op_CXNN(0x{:X}, 0x{:02X})", x, nn),
+        Instruction::OpDXYN(x, y, n) => format!("op_DXYN(0x{:X}, 0x{:X}, {})", x, y, n),
+        Instruction::OpEX9E(x) => format!("op_EX9E(0x{:X})", x),
+        Instruction::OpEXA1(x) => format!("op_EXA1(0x{:X})", x),
+        Instruction::OpFX07(x) => format!("op_FX07(0x{:X})", x),
+        Instruction::OpFX0A(x) => format!("op_FX0A(0x{:X})", x),
+        Instruction::OpFX15(x) => format!("op_FX15(0x{:X})", x),
+        Instruction::OpFX18(x) => format!("op_FX18(0x{:X})", x),
+        Instruction::OpFX1E(x) => format!("op_FX1E(0x{:X})", x),
+        Instruction::OpFX29(x) => format!("op_FX29(0x{:X})", x),
+        Instruction::OpFX33(x) => format!("op_FX33(0x{:X})", x),
+        Instruction::OpFX55(x) => format!("op_FX55(0x{:X})", x),
+        Instruction::OpFX65(x) => format!("op_FX65(0x{:X})", x),
+        Instruction::Invalid(opcode) => panic!("Cannot compile opcode {:04X}", opcode),
+    }
+}
+
+fn read_opcode(rom: &[u8], address: u16) -> u16 {
+    let i = (address - Recompiler::START_ADDRESS) as usize;
+    ((rom[i] as u16) << 8) | *rom.get(i + 1).unwrap_or(&0) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // Output of generate for a ROM whose loop stores through a computed I into
+    // the block after the store. Regenerate it with `recompile` when
+    // generated_module_is_up_to_date fails.
+    mod recompiled {
+        include!("../testdata/recompiled.rs");
+    }
+
+    #[test]
+    fn generated_module_is_up_to_date() {
+        let source = Recompiler::new(&recompiled::ROM).generate("lockstep.ch8", &SymbolMap::new());
+        assert_eq!(source, include_str!("../testdata/recompiled.rs"));
+    }
+
+    #[test]
+    fn generated_module_runs_in_lockstep_with_the_interpreter() {
+        let mut compiled = recompiled::new_chip();
+        let mut interpreted = Chip8::new(&recompiled::ROM.to_vec());
+
+        for _ in 0..1000 {
+            for _ in 0..recompiled::run(&mut compiled, 1) {
+                interpreted.cycle();
+            }
+            assert_eq!(compiled.get_pc(), interpreted.get_pc());
+            assert_eq!(compiled.get_index_register(), interpreted.get_index_register());
+            assert_eq!(compiled.get_registers(), interpreted.get_registers());
+        }
+        assert_eq!(compiled.get_memory(0x20F), interpreted.get_memory(0x20F));
+    }
+
+    #[test]
+    fn guarded_blocks_end_after_a_store_to_an_unknown_address() {
+        let recompiler = Recompiler::new(&recompiled::ROM);
+        assert!(recompiler.guard_blocks);
+        let block: Vec<u16> = recompiler.block_instructions(0x208).iter().map(|(a, _)| *a).collect();
+        assert_eq!(block, vec![0x208, 0x20A]);
+        assert!(recompiler.block_leaders().contains(&0x20C));
+    }
+}
@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::chip8::Chip8;
+use crate::palette::Palette;
+
+// Records one image per emulated 60 Hz frame, as an animated GIF or as an
+// uncompressed YUV4MPEG2 stream for external encoders. The format follows
+// the file extension.
+pub struct Recorder {
+    path: String,
+    writer: Writer,
+    palette: Palette,
+    scale: usize,
+    frames: u64,
+}
+
+enum Writer {
+    // Identical consecutive frames are merged into one with a longer delay,
+    // so the pending frame is written once the next different one arrives.
+    // Browsers show delays under 2 cs as 10 cs, so an image that would be
+    // shown for less is replaced by the next one, which starts in its place.
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        pending: Option<(Vec<u8>, u64)>,
+    },
+    Y4m(BufWriter<File>),
+}
+
+impl Recorder {
+    // GIF sizes are 16 bits
+    const MAX_SCALE: usize = u16::MAX as usize / Chip8::VIDEO_WIDTH;
+
+    pub fn start(path: &str, palette: &Palette, scale: usize) -> Result<Recorder, String> {
+        if scale == 0 || scale > Recorder::MAX_SCALE {
+            return Err(format!("Invalid recording scale {}, expected 1 to {}", scale, Recorder::MAX_SCALE));
+        }
+        let width = Chip8::VIDEO_WIDTH * scale;
+        let height = Chip8::VIDEO_HEIGHT * scale;
+        let file = match File::create(path) {
+            Ok(f) => BufWriter::new(f),
+            Err(e) => return Err(format!("Error creating {}: {}", path, e)),
+        };
+
+        let writer = if path.ends_with(".gif") {
+            let colors: Vec<u8> = palette.colors.iter().flat_map(|c| [c.0, c.1, c.2]).collect();
+            let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &colors)
+                .map_err(|e| format!("Error writing {}: {}", path, e))?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| format!("Error writing {}: {}", path, e))?;
+            Writer::Gif { encoder, pending: None }
+        } else if path.ends_with(".y4m") {
+            let mut file = file;
+            writeln!(file, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", width, height)
+                .map_err(|e| format!("Error writing {}: {}", path, e))?;
+            Writer::Y4m(file)
+        } else {
+            return Err(format!("Unknown recording format {}, expected .gif or .y4m", path));
+        };
+
+        Ok(Recorder {
+            path: path.to_string(),
+            writer,
+            palette: palette.clone(),
+            scale,
+            frames: 0,
+        })
+    }
+
+    // Appends the current framebuffer as the next frame
+    pub fn add_frame(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let indices = self.indices(chip8);
+        let frame = self.frames;
+        self.frames += 1;
+
+        match &mut self.writer {
+            Writer::Gif { encoder, pending } => {
+                if let Some((previous, first)) = pending {
+                    if *previous == indices {
+                        return Ok(());
+                    }
+                    if centiseconds(frame) - centiseconds(*first) < MIN_GIF_DELAY {
+                        *previous = indices;
+                        return Ok(());
+                    }
+                }
+                if let Some((previous, first)) = pending.replace((indices, frame)) {
+                    write_gif_frame(encoder, &previous, first, frame, self.scale)
+                        .map_err(|e| format!("Error writing {}: {}", self.path, e))?;
+                }
+                Ok(())
+            }
+            Writer::Y4m(file) => {
+                let palette: Vec<(u8, u8, u8)> = self.palette.colors.iter().map(|c| ycbcr(*c)).collect();
+                let mut data = Vec::with_capacity(6 + indices.len() * 3);
+                data.extend_from_slice(b"FRAME\n");
+                data.extend(indices.iter().map(|i| palette[*i as usize].0));
+                data.extend(indices.iter().map(|i| palette[*i as usize].1));
+                data.extend(indices.iter().map(|i| palette[*i as usize].2));
+                file.write_all(&data).map_err(|e| format!("Error writing {}: {}", self.path, e))
+            }
+        }
+    }
+
+    // Flushes the file and returns its path and the number of frames recorded
+    pub fn finish(self) -> Result<(String, u64), String> {
+        let error = |e: std::io::Error| format!("Error writing {}: {}", self.path, e);
+        match self.writer {
+            Writer::Gif { mut encoder, pending } => {
+                if let Some((previous, first)) = pending {
+                    write_gif_frame(&mut encoder, &previous, first, self.frames, self.scale)
+                        .map_err(|e| format!("Error writing {}: {}", self.path, e))?;
+                }
+                encoder.into_inner().map_err(error)?.flush().map_err(error)?;
+            }
+            Writer::Y4m(mut file) => file.flush().map_err(error)?,
+        }
+        Ok((self.path.clone(), self.frames))
+    }
+
+    // Palette index of every output pixel, scaled up
+    fn indices(&self, chip8: &Chip8) -> Vec<u8> {
+        let width = Chip8::VIDEO_WIDTH * self.scale;
+        let mut indices = Vec::with_capacity(width * Chip8::VIDEO_HEIGHT * self.scale);
+        for y in 0..Chip8::VIDEO_HEIGHT {
+            let start = indices.len();
+            for x in 0..Chip8::VIDEO_WIDTH {
+                let value = chip8.get_video(x, y) as u8;
+                indices.extend(std::iter::repeat_n(value, self.scale));
+            }
+            for _ in 1..self.scale {
+                indices.extend_from_within(start..start + width);
+            }
+        }
+        indices
+    }
+}
+
+// Shortest GIF delay browsers play as written, in hundredths of a second
+const MIN_GIF_DELAY: u64 = 2;
+
+// Time frame starts at in hundredths of a second. Delays are rounded against
+// this running total to keep the animation at 60 Hz overall.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + 30) / 60
+}
+
+// Writes the image shown from frame first up to frame end. Only the last
+// image can be shorter than MIN_GIF_DELAY, and is stretched to it.
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    indices: &[u8],
+    first: u64,
+    end: u64,
+    scale: usize,
+) -> Result<(), gif::EncodingError> {
+    let frame = gif::Frame {
+        width: (Chip8::VIDEO_WIDTH * scale) as u16,
+        height: (Chip8::VIDEO_HEIGHT * scale) as u16,
+        delay: (centiseconds(end) - centiseconds(first)).clamp(MIN_GIF_DELAY, u16::MAX as u64) as u16,
+        buffer: Cow::Borrowed(indices),
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame)
+}
+
+// BT.601 limited range, as most Y4M consumers expect
+fn ycbcr(color: (u8, u8, u8)) -> (u8, u8, u8) {
+    let (r, g, b) = (color.0 as f32, color.1 as f32, color.2 as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    (y.round() as u8, cb.round() as u8, cr.round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records frames with the top left pixel lit in the frames listed, returning the GIF's delays.
+    // Each call needs its own name since tests run in parallel.
+    fn record(name: &str, lit: &[bool]) -> Vec<u16> {
+        let path = std::env::temp_dir().join(format!("recorder_test_{}_{}.gif", std::process::id(), name));
+        let path = path.to_str().unwrap();
+        let mut chip = Chip8::new(&vec![0x80]);
+        chip.op_ANNN(0x200);
+        let mut recorder = Recorder::start(path, &Palette::named("classic").unwrap(), 1).unwrap();
+        for &on in lit {
+            if (chip.get_video(0, 0) != 0) != on {
+                chip.op_DXYN(0, 0, 1);
+            }
+            recorder.add_frame(&chip).unwrap();
+        }
+        assert_eq!(recorder.finish().unwrap().1, lit.len() as u64);
+
+        let mut decoder = gif::DecodeOptions::new().read_info(File::open(path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        std::fs::remove_file(path).unwrap();
+        delays
+    }
+
+    #[test]
+    fn merges_identical_frames() {
+        // 0-2 cs, 2-5 cs, 5-10 cs
+        assert_eq!(record("merges", &[false, true, true, false, false, false]), vec![2, 3, 5]);
+    }
+
+    #[test]
+    fn keeps_every_delay_at_least_two_hundredths() {
+        // Flickering every frame, which starts at 0, 2, 3, 5, 7 and 8 cs. Frames
+        // 1 and 4 would show for 1 cs, so frames 2 and 5 replace them.
+        let delays = record("flicker", &[false, true, false, true, false, true]);
+        assert_eq!(delays, vec![2, 3, 2, 3]);
+        // The last frame is stretched
+        assert_eq!(record("single", &[true]), vec![2]);
+    }
+
+    #[test]
+    fn rejects_scales_beyond_gif_sizes() {
+        let palette = Palette::named("classic").unwrap();
+        assert!(Recorder::start("unused.gif", &palette, 0).is_err());
+        assert!(Recorder::start("unused.gif", &palette, 1024).is_err());
+    }
+}
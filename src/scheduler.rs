@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+// Paces emulation against a monotonic clock. Time is split into 60 Hz frames;
+// each frame runs its share of the instructions per second, then ticks the
+// timers and presents. Deadlines are computed from a fixed base instant, so
+// sleeping too long on one frame is made up on the next instead of drifting.
+pub struct Scheduler {
+    ips: u64,
+    // Frame base_frame was due at base, later frames follow at the current speed
+    base: Instant,
+    base_frame: u64,
+    // Frames started so far
+    frames: u64,
+
+    paused: bool,
+    // Frames to run while paused
+    advance: u64,
+    // Run frames back to back without waiting
+    fast_forward: bool,
+    // Percentage of real time, 100, 50 or 25
+    speed: u64,
+}
+
+impl Scheduler {
+    pub const FRAME_RATE: u64 = 60;
+    // Frames run back to back when behind. Anything older is dropped, so a
+    // stall (debugger prompt, window drag) doesn't fast-forward the game.
+    const MAX_CATCH_UP: u64 = 6;
+    const FRAME_NANOS: u64 = 1_000_000_000 / Scheduler::FRAME_RATE;
+
+    pub fn new(ips: u64) -> Scheduler {
+        Scheduler {
+            ips,
+            base: Instant::now(),
+            base_frame: 0,
+            frames: 0,
+            paused: false,
+            advance: 0,
+            fast_forward: false,
+            speed: 100,
+        }
+    }
+
+    // Number of frames due now, each to be run with next_frame
+    pub fn frames_due(&mut self) -> u64 {
+        if self.paused {
+            return std::mem::replace(&mut self.advance, 0);
+        }
+        if self.fast_forward {
+            return 1;
+        }
+
+        let elapsed = self.base.elapsed().as_nanos() as u64 * self.speed / 100;
+        let target = self.base_frame + elapsed / Scheduler::FRAME_NANOS + 1;
+        let due = target.saturating_sub(self.frames);
+        if due > Scheduler::MAX_CATCH_UP {
+            self.frames += due - Scheduler::MAX_CATCH_UP;
+            return Scheduler::MAX_CATCH_UP;
+        }
+        due
+    }
+
+    // Starts the next frame and returns how many instructions it runs. Counts
+    // are rounded per frame so they add up to exactly ips every second. The
+    // products are 128-bit so no --ips overflows them.
+    pub fn next_frame(&mut self) -> u64 {
+        let frame = self.frames as u128;
+        let ips = self.ips as u128;
+        let rate = Scheduler::FRAME_RATE as u128;
+        self.frames += 1;
+        ((frame + 1) * ips / rate - frame * ips / rate) as u64
+    }
+
+    // Sleeps until the next frame is due
+    pub fn wait(&self) {
+        if self.fast_forward && !self.paused {
+            return;
+        }
+        if self.paused {
+            // Only events can change anything, poll them once a frame
+            std::thread::sleep(Duration::from_nanos(Scheduler::FRAME_NANOS));
+            return;
+        }
+
+        let scheduled = (self.frames - self.base_frame) * Scheduler::FRAME_NANOS * 100 / self.speed;
+        let deadline = self.base + Duration::from_nanos(scheduled);
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.rebase();
+    }
+
+    // Pauses if needed and runs one more frame
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance += 1;
+    }
+
+    pub fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+        self.rebase();
+    }
+
+    // 100% -> 50% -> 25% -> 100%
+    pub fn cycle_slow_motion(&mut self) {
+        self.speed = match self.speed {
+            100 => 50,
+            50 => 25,
+            _ => 100,
+        };
+        self.rebase();
+    }
+
+    // Current state for the window title, empty when running normally
+    pub fn describe(&self) -> String {
+        if self.paused {
+            "paused".to_string()
+        } else if self.fast_forward {
+            "fast forward".to_string()
+        } else if self.speed != 100 {
+            format!("{}%", self.speed)
+        } else {
+            String::new()
+        }
+    }
+
+    // Restarts the schedule from now, so time spent paused or at another
+    // speed isn't caught up
+    fn rebase(&mut self) {
+        self.base = Instant::now();
+        self.base_frame = self.frames;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_instructions_evenly_over_a_second() {
+        for ips in [1, 100, 700, 1000, 1_000_000] {
+            let mut scheduler = Scheduler::new(ips);
+            let counts: Vec<u64> = (0..Scheduler::FRAME_RATE).map(|_| scheduler.next_frame()).collect();
+            assert_eq!(counts.iter().sum::<u64>(), ips, "{}", ips);
+            let low = ips / Scheduler::FRAME_RATE;
+            assert!(counts.iter().all(|&c| c == low || c == low + 1), "{}", ips);
+        }
+    }
+
+    #[test]
+    fn handles_the_largest_ips() {
+        let mut scheduler = Scheduler::new(u64::MAX);
+        scheduler.frames = u64::MAX / 2;
+        let count = scheduler.next_frame();
+        assert!(count == u64::MAX / Scheduler::FRAME_RATE || count == u64::MAX / Scheduler::FRAME_RATE + 1);
+    }
+
+    #[test]
+    fn starts_with_one_frame_due() {
+        let mut scheduler = Scheduler::new(700);
+        // No time has passed as long as the base is in the future
+        scheduler.base = Instant::now() + Duration::from_secs(60);
+        assert_eq!(scheduler.frames_due(), 1);
+        scheduler.next_frame();
+        assert_eq!(scheduler.frames_due(), 0);
+    }
+
+    #[test]
+    fn caps_catch_up_and_drops_older_frames() {
+        let mut scheduler = Scheduler::new(700);
+        scheduler.base = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+
+        assert_eq!(scheduler.frames_due(), Scheduler::MAX_CATCH_UP);
+        for _ in 0..Scheduler::MAX_CATCH_UP {
+            scheduler.next_frame();
+        }
+        // At most one more if a frame boundary passed meanwhile
+        assert!(scheduler.frames_due() <= 1);
+        assert!(scheduler.frames >= Scheduler::FRAME_RATE);
+    }
+
+    #[test]
+    fn runs_only_advanced_frames_while_paused() {
+        let mut scheduler = Scheduler::new(700);
+        scheduler.toggle_pause();
+        assert_eq!(scheduler.frames_due(), 0);
+        scheduler.advance_frame();
+        scheduler.advance_frame();
+        assert_eq!(scheduler.frames_due(), 2);
+        assert_eq!(scheduler.frames_due(), 0);
+        assert_eq!(scheduler.describe(), "paused");
+    }
+
+    #[test]
+    fn fast_forward_always_has_a_frame_due() {
+        let mut scheduler = Scheduler::new(700);
+        scheduler.set_fast_forward(true);
+        for _ in 0..10 {
+            assert_eq!(scheduler.frames_due(), 1);
+            scheduler.next_frame();
+        }
+        assert_eq!(scheduler.describe(), "fast forward");
+    }
+}
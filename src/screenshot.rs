@@ -0,0 +1,51 @@
+use crate::chip8::Chip8;
+use crate::image::Image;
+use crate::palette::Palette;
+
+// The framebuffer as an image, each emulated pixel scale x scale in size
+pub fn render(chip8: &Chip8, palette: &Palette, scale: usize) -> Image {
+    let mut image = Image::new(Chip8::VIDEO_WIDTH * scale, Chip8::VIDEO_HEIGHT * scale, palette.color(0));
+    for y in 0..Chip8::VIDEO_HEIGHT {
+        for x in 0..Chip8::VIDEO_WIDTH {
+            let pixel = chip8.get_video(x, y);
+            if pixel != 0 {
+                image.fill_rect(x * scale, y * scale, scale, scale, palette.color(pixel));
+            }
+        }
+    }
+    image
+}
+
+// Saves the framebuffer as <prefix>_frame<frame>.png and returns the path
+pub fn save(chip8: &Chip8, palette: &Palette, scale: usize, prefix: &str, frame: u64) -> Result<String, String> {
+    let path = format!("{}_frame{}.png", prefix, frame);
+    render(chip8, palette, scale).save_png(&path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_each_pixel_as_a_square_in_palette_colors() {
+        let mut chip = Chip8::new(&vec![0x80]);
+        chip.op_ANNN(0x200);
+        chip.op_6XNN(0, 3);
+        chip.op_6XNN(1, 2);
+        chip.op_DXYN(0, 1, 1);
+        let palette = Palette::named("amber").unwrap();
+
+        let image = render(&chip, &palette, 3);
+        assert_eq!((image.width, image.height), (Chip8::VIDEO_WIDTH * 3, Chip8::VIDEO_HEIGHT * 3));
+        let on = palette.color(chip.get_video(3, 2));
+        let off = palette.color(0);
+        assert_ne!(on, off);
+        for (x, y) in [(9, 6), (11, 6), (9, 8), (11, 8)] {
+            assert_eq!(image.get_pixel(x, y), on);
+        }
+        for (x, y) in [(8, 6), (12, 6), (9, 5), (9, 9), (0, 0)] {
+            assert_eq!(image.get_pixel(x, y), off);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+extern crate sdl2;
+
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::sys::{SDL_GetTouchDeviceType, SDL_TouchDeviceType};
+
+use crate::frontend::{InputEvent, InputSource, RendererCommand};
+use crate::sprite_viewer::ViewerCommand;
+
+// Keyboard and window events from SDL.
+/*
+Keypad       Keyboard
++-+-+-+-+    +-+-+-+-+
+|1|2|3|C|    |1|2|3|4|
++-+-+-+-+    +-+-+-+-+
+|4|5|6|D|    |Q|W|E|R|
++-+-+-+-+ => +-+-+-+-+
+|7|8|9|E|    |A|S|D|F|
++-+-+-+-+    +-+-+-+-+
+|A|0|B|F|    |Z|X|C|V|
++-+-+-+-+    +-+-+-+-+
+
+P      pause / resume
+N      advance one frame
+M      advance one instruction
+Tab    fast forward while held
+Minus  slow motion 100% -> 50% -> 25%
+F10    integer scaling / stretch
+F11    fullscreen
+F9     next palette
+F12    screenshot
+F8     start / stop recording a GIF
+F1     show frame and instruction rates
+F2     show registers
+F3     show the on-screen keypad, which also takes clicks and touches
+F4     mute / unmute the beeper
+*/
+pub struct SdlInput {
+    event_pump: sdl2::EventPump,
+    // Main window size, to turn mouse positions into fractions of it
+    window_size: (u32, u32),
+    // Events for this window control the sprite viewer
+    viewer_window: Option<u32>,
+    // Set by enable_rumble, controllers are opened as they are attached
+    controller_subsystem: Option<sdl2::GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+    rumbling: bool,
+}
+
+impl SdlInput {
+    // Pointer id of the mouse, finger ids come from SDL
+    const MOUSE: i64 = -1;
+
+    pub fn new(context: &sdl2::Sdl, window_size: (u32, u32), viewer_window: Option<u32>) -> SdlInput {
+        SdlInput {
+            event_pump: context.event_pump().unwrap(),
+            window_size,
+            controller_subsystem: None,
+            controllers: Vec::new(),
+            rumbling: false,
+            viewer_window,
+        }
+    }
+
+    // Opens game controllers for set_rumble, including ones attached later.
+    // Without the subsystem nothing rumbles.
+    pub fn enable_rumble(&mut self, context: &sdl2::Sdl) -> Result<(), String> {
+        self.controller_subsystem = Some(context.game_controller()?);
+        Ok(())
+    }
+
+    // SDL also reports controllers attached at startup this way
+    fn add_controller(&mut self, joystick_index: u32) {
+        let subsystem = match &self.controller_subsystem {
+            Some(s) => s,
+            None => return,
+        };
+        if let Ok(mut controller) = subsystem.open(joystick_index) {
+            if self.rumbling {
+                rumble(&mut controller, true);
+            }
+            self.controllers.push(controller);
+        }
+    }
+
+    fn mouse_event(&self, x: i32, y: i32, pressed: bool) -> InputEvent {
+        let (width, height) = self.window_size;
+        InputEvent::Pointer(SdlInput::MOUSE, x as f32 / width.max(1) as f32, y as f32 / height.max(1) as f32, pressed)
+    }
+
+    fn viewer_event(&mut self, event: Event) -> Option<InputEvent> {
+        let command = match event {
+            Event::Window { win_event: WindowEvent::Close, .. } => {
+                self.viewer_window = None;
+                ViewerCommand::Close
+            }
+            Event::Window { win_event: WindowEvent::Exposed, .. } => ViewerCommand::Redraw,
+            Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
+                Keycode::Up => ViewerCommand::TallerSprites,
+                Keycode::Down => ViewerCommand::ShorterSprites,
+                Keycode::H => ViewerCommand::ToggleWide,
+                Keycode::Left => ViewerCommand::Scroll(-1),
+                Keycode::Right => ViewerCommand::Scroll(1),
+                Keycode::PageUp => ViewerCommand::PageUp,
+                Keycode::PageDown => ViewerCommand::PageDown,
+                Keycode::Return => ViewerCommand::Export,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(InputEvent::Renderer(RendererCommand::SpriteViewer(command)))
+    }
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let pending: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in pending {
+            if self.viewer_window.is_some() && event.get_window_id() == self.viewer_window {
+                events.extend(self.viewer_event(event));
+                continue;
+            }
+
+            let input = match event {
+                Event::Quit {..} => InputEvent::Quit,
+                Event::Window { win_event: WindowEvent::Close, .. } => InputEvent::Quit,
+                Event::Window { win_event: WindowEvent::Exposed, .. } => InputEvent::Redraw,
+                Event::Window { win_event: WindowEvent::SizeChanged(width, height), .. } => {
+                    self.window_size = (width as u32, height as u32);
+                    InputEvent::Resized(width as u32, height as u32)
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    self.add_controller(which);
+                    continue;
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|c| c.instance_id() != which);
+                    continue;
+                }
+                // SDL also turns touches into mouse events, which come from SDL_TOUCH_MOUSEID
+                Event::MouseButtonDown { which, mouse_btn: MouseButton::Left, x, y, .. } if which != u32::MAX => {
+                    self.mouse_event(x, y, true)
+                }
+                Event::MouseButtonUp { which, mouse_btn: MouseButton::Left, x, y, .. } if which != u32::MAX => {
+                    self.mouse_event(x, y, false)
+                }
+                // Positions are already fractions of the window
+                Event::FingerDown { touch_id, finger_id, x, y, .. } if is_touchscreen(touch_id) => {
+                    InputEvent::Pointer(finger_id, x, y, true)
+                }
+                Event::FingerUp { touch_id, finger_id, x, y, .. } if is_touchscreen(touch_id) => {
+                    InputEvent::Pointer(finger_id, x, y, false)
+                }
+                Event::KeyDown { keycode: Some(keycode), repeat, .. } => match keycode {
+                    Keycode::Escape => InputEvent::Quit,
+                    Keycode::P if !repeat => InputEvent::TogglePause,
+                    Keycode::N => InputEvent::AdvanceFrame,
+                    Keycode::M => InputEvent::AdvanceInstruction,
+                    Keycode::Tab if !repeat => InputEvent::FastForward(true),
+                    Keycode::Minus if !repeat => InputEvent::SlowMotion,
+                    Keycode::F1 if !repeat => InputEvent::Renderer(RendererCommand::ToggleStats),
+                    Keycode::F2 if !repeat => InputEvent::Renderer(RendererCommand::ToggleRegisters),
+                    Keycode::F3 if !repeat => InputEvent::Renderer(RendererCommand::ToggleKeypad),
+                    Keycode::F4 if !repeat => InputEvent::ToggleMute,
+                    Keycode::F8 if !repeat => InputEvent::ToggleRecording,
+                    Keycode::F9 if !repeat => InputEvent::NextPalette,
+                    Keycode::F10 if !repeat => InputEvent::Renderer(RendererCommand::ToggleScaling),
+                    Keycode::F11 if !repeat => InputEvent::Renderer(RendererCommand::ToggleFullscreen),
+                    Keycode::F12 if !repeat => InputEvent::Screenshot,
+                    _ => match keypad_index(keycode) {
+                        Some(key) => InputEvent::Key(key, true),
+                        None => continue,
+                    },
+                },
+                Event::KeyUp { keycode: Some(keycode), .. } => match keycode {
+                    Keycode::Tab => InputEvent::FastForward(false),
+                    _ => match keypad_index(keycode) {
+                        Some(key) => InputEvent::Key(key, false),
+                        None => continue,
+                    },
+                },
+                _ => continue,
+            };
+            events.push(input);
+        }
+        events
+    }
+
+    // Renewed every call while on, stopped once when it turns off
+    fn set_rumble(&mut self, on: bool) {
+        if on || self.rumbling {
+            for controller in self.controllers.iter_mut() {
+                rumble(controller, on);
+            }
+        }
+        self.rumbling = on;
+    }
+}
+
+// Controllers without rumble motors just fail, which is fine
+fn rumble(controller: &mut GameController, on: bool) {
+    // Long enough to bridge a few frames, short enough to stop soon after the
+    // loop stops renewing it, e.g. in the debugger
+    let _ = if on {
+        controller.set_rumble(0x8000, 0x8000, 100)
+    } else {
+        controller.set_rumble(0, 0, 0)
+    };
+}
+
+// Touchpads report fingers too, but their positions aren't on the window
+fn is_touchscreen(touch_id: i64) -> bool {
+    let device_type = unsafe { SDL_GetTouchDeviceType(touch_id) };
+    device_type == SDL_TouchDeviceType::SDL_TOUCH_DEVICE_DIRECT
+}
+
+fn keypad_index(keycode: Keycode) -> Option<u8> {
+    let key = match keycode {
+        Keycode::X => 0x0,
+        Keycode::Num1 => 0x1,
+        Keycode::Num2 => 0x2,
+        Keycode::Num3 => 0x3,
+        Keycode::Q => 0x4,
+        Keycode::W => 0x5,
+        Keycode::E => 0x6,
+        Keycode::A => 0x7,
+        Keycode::S => 0x8,
+        Keycode::D => 0x9,
+        Keycode::Z => 0xA,
+        Keycode::C => 0xB,
+        Keycode::Num4 => 0xC,
+        Keycode::R => 0xD,
+        Keycode::F => 0xE,
+        Keycode::V => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
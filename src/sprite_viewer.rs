@@ -0,0 +1,127 @@
+extern crate sdl2;
+
+use sdl2::pixels::PixelFormatEnum;
+
+use crate::chip8;
+use crate::sprites::SpriteSheet;
+
+// Controls of the sprite viewer, bound to keys in its window by SdlInput:
+//   Up/Down          sprite height
+//   H                toggle 16x16 SCHIP sprites
+//   Left/Right       move the range by one byte
+//   PageUp/PageDown  move the range by one row of sprites
+//   Return           export the sheet as PNG
+// Redraw comes from the window being exposed.
+pub enum ViewerCommand {
+    TallerSprites,
+    ShorterSprites,
+    ToggleWide,
+    Scroll(i32),
+    PageUp,
+    PageDown,
+    Export,
+    Redraw,
+    Close,
+}
+
+// Tool window showing a memory range as sprites
+pub struct SpriteViewer {
+    sheet: SpriteSheet,
+    canvas: sdl2::render::WindowCanvas,
+    texture_creator: sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+    // Sized to the sheet, made again when the sheet's size changes
+    texture: Option<sdl2::render::Texture>,
+    // Sheet last drawn, to skip drawing it again unchanged
+    shown: Vec<u8>,
+    // Title or window contents need drawing whether or not the sheet changed
+    stale: bool,
+    export_prefix: String,
+}
+
+impl SpriteViewer {
+    const SCALE: u32 = 4;
+    const EXPORT_SCALE: usize = 8;
+
+    pub fn new(context: &sdl2::Sdl, sheet: SpriteSheet, export_prefix: &str) -> SpriteViewer {
+        let video_subsystem = context.video().unwrap();
+        let window = video_subsystem.window("Sprites", 1, 1)
+            .position_centered()
+            .build()
+            .unwrap();
+
+        let canvas = window.into_canvas().build().unwrap();
+        let texture_creator = canvas.texture_creator();
+
+        SpriteViewer {
+            sheet,
+            canvas,
+            texture_creator,
+            texture: None,
+            shown: Vec::new(),
+            stale: true,
+            export_prefix: export_prefix.to_string(),
+        }
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Close is handled by the owner, which drops the viewer
+    pub fn handle(&mut self, command: ViewerCommand, chip8: &chip8::Chip8) {
+        let page = SpriteSheet::SPRITES_PER_ROW as i32 * if self.sheet.wide { 32 } else { self.sheet.height as i32 };
+        match command {
+            ViewerCommand::TallerSprites if self.sheet.height < SpriteSheet::MAX_HEIGHT => self.sheet.height += 1,
+            ViewerCommand::ShorterSprites if self.sheet.height > 1 => self.sheet.height -= 1,
+            ViewerCommand::ToggleWide => self.sheet.wide = !self.sheet.wide,
+            ViewerCommand::Scroll(delta) => self.sheet.scroll(delta),
+            ViewerCommand::PageUp => self.sheet.scroll(-page),
+            ViewerCommand::PageDown => self.sheet.scroll(page),
+            ViewerCommand::Redraw => {}
+            ViewerCommand::Export => {
+                let path = format!("{}_sprites_{:03X}.png", self.export_prefix, self.sheet.start);
+                match self.sheet.export_png(chip8, &path, SpriteViewer::EXPORT_SCALE) {
+                    Ok(()) => println!("Exported sprites to {}", path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            _ => return,
+        }
+        self.stale = true;
+    }
+
+    // Draws the sheet if it or the view changed since the last draw
+    pub fn draw(&mut self, chip8: &chip8::Chip8) {
+        let image = self.sheet.render(chip8);
+        if !self.stale && image.pixels() == self.shown.as_slice() {
+            return;
+        }
+        let width = image.width as u32;
+        let height = image.height as u32;
+
+        let window = self.canvas.window_mut();
+        if window.size() != (width * SpriteViewer::SCALE, height * SpriteViewer::SCALE) {
+            window.set_size(width * SpriteViewer::SCALE, height * SpriteViewer::SCALE).unwrap();
+        }
+        window.set_title(&format!("Sprites {}", self.sheet.describe())).unwrap();
+
+        let size = self.texture.as_ref().map(|t| (t.query().width, t.query().height));
+        if size != Some((width, height)) {
+            if let Some(texture) = self.texture.take() {
+                // Only ever drawn with this viewer's canvas, which is still alive
+                unsafe { texture.destroy() };
+            }
+            self.texture = Some(self.texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .unwrap());
+        }
+        let texture = self.texture.as_mut().unwrap();
+        texture.update(None, image.pixels(), image.width * 3).unwrap();
+
+        self.canvas.clear();
+        self.canvas.copy(texture, None, None).unwrap();
+        self.canvas.present();
+        self.shown = image.pixels().to_vec();
+        self.stale = false;
+    }
+}
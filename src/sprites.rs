@@ -0,0 +1,220 @@
+use crate::chip8::Chip8;
+use crate::image::Image;
+
+// Lays out a memory range as a grid of sprites. Bytes that op_DXYN has drawn
+// from during the run are highlighted.
+pub struct SpriteSheet {
+    pub start: u16,
+    // Exclusive
+    pub end: u16,
+    // Rows per 8-pixel-wide sprite
+    pub height: u8,
+    // 16x16 SCHIP sprites (two bytes per row, sixteen rows)
+    pub wide: bool,
+}
+
+impl SpriteSheet {
+    pub const SPRITES_PER_ROW: usize = 16;
+    pub const MAX_HEIGHT: u8 = 15;
+    const GAP: usize = 1;
+
+    const COLOR_GAP: (u8, u8, u8) = (48, 48, 48);
+    const COLOR_OFF: (u8, u8, u8) = (0, 0, 0);
+    const COLOR_ON: (u8, u8, u8) = (255, 255, 255);
+    const COLOR_DRAWN_OFF: (u8, u8, u8) = (0, 40, 80);
+    const COLOR_DRAWN_ON: (u8, u8, u8) = (255, 200, 0);
+
+    // Parses "START:END[:HEIGHT]", resolving START and END with the given function
+    pub fn parse(text: &str, resolve: impl Fn(&str) -> Option<u16>) -> Option<SpriteSheet> {
+        let fields: Vec<&str> = text.split(':').collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return None;
+        }
+
+        let start = resolve(fields[0])?;
+        let end = resolve(fields[1])?;
+        let height = match fields.get(2) {
+            Some(h) => h.parse::<u8>().ok()?,
+            None => 8,
+        };
+
+        if start >= end || end > 0x1000 || height == 0 || height > SpriteSheet::MAX_HEIGHT {
+            return None;
+        }
+
+        Some(SpriteSheet { start, end, height, wide: false })
+    }
+
+    pub fn sprite_width(&self) -> usize {
+        if self.wide { 16 } else { 8 }
+    }
+
+    pub fn sprite_height(&self) -> usize {
+        if self.wide { 16 } else { self.height as usize }
+    }
+
+    fn sprite_bytes(&self) -> usize {
+        if self.wide { 32 } else { self.height as usize }
+    }
+
+    pub fn sprite_count(&self) -> usize {
+        let length = (self.end - self.start) as usize;
+        length.div_ceil(self.sprite_bytes())
+    }
+
+    // Moves the range by delta bytes, keeping its length
+    pub fn scroll(&mut self, delta: i32) {
+        let length = (self.end - self.start) as i32;
+        let start = (self.start as i32 + delta).clamp(0, 0x1000 - length);
+        self.start = start as u16;
+        self.end = (start + length) as u16;
+    }
+
+    pub fn render(&self, chip: &Chip8) -> Image {
+        let cell_width = self.sprite_width() + SpriteSheet::GAP;
+        let cell_height = self.sprite_height() + SpriteSheet::GAP;
+        let count = self.sprite_count();
+        let columns = count.min(SpriteSheet::SPRITES_PER_ROW);
+        let rows = count.div_ceil(SpriteSheet::SPRITES_PER_ROW);
+
+        let mut image = Image::new(
+            columns * cell_width + SpriteSheet::GAP,
+            rows * cell_height + SpriteSheet::GAP,
+            SpriteSheet::COLOR_GAP,
+        );
+
+        let bytes_per_row = self.sprite_width() / 8;
+        for sprite in 0..count {
+            let left = (sprite % SpriteSheet::SPRITES_PER_ROW) * cell_width + SpriteSheet::GAP;
+            let top = (sprite / SpriteSheet::SPRITES_PER_ROW) * cell_height + SpriteSheet::GAP;
+            let base = self.start as usize + sprite * self.sprite_bytes();
+
+            for row in 0..self.sprite_height() {
+                for byte in 0..bytes_per_row {
+                    let address = base + row * bytes_per_row + byte;
+                    if address >= self.end as usize {
+                        continue;
+                    }
+
+                    let value = chip.get_memory(address as u16);
+                    let drawn = chip.was_drawn_from(address as u16);
+                    for bit in 0..8 {
+                        let on = value & (0x80 >> bit) != 0;
+                        let color = match (on, drawn) {
+                            (true, true) => SpriteSheet::COLOR_DRAWN_ON,
+                            (false, true) => SpriteSheet::COLOR_DRAWN_OFF,
+                            (true, false) => SpriteSheet::COLOR_ON,
+                            (false, false) => SpriteSheet::COLOR_OFF,
+                        };
+                        image.set_pixel(left + byte * 8 + bit, top + row, color);
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    // Sheet image scaled up by an integer factor
+    pub fn export_png(&self, chip: &Chip8, path: &str, scale: usize) -> Result<(), String> {
+        let sheet = self.render(chip);
+        let mut scaled = Image::new(sheet.width * scale, sheet.height * scale, SpriteSheet::COLOR_GAP);
+        for y in 0..sheet.height {
+            for x in 0..sheet.width {
+                scaled.fill_rect(x * scale, y * scale, scale, scale, sheet.get_pixel(x, y));
+            }
+        }
+
+        scaled.save_png(path)
+    }
+
+    pub fn describe(&self) -> String {
+        if self.wide {
+            format!("{:03X}-{:03X} 16x16", self.start, self.end)
+        } else {
+            format!("{:03X}-{:03X} 8x{}", self.start, self.end, self.height)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(text: &str) -> Option<u16> {
+        match text {
+            "tiles" => Some(0x300),
+            "tiles_end" => Some(0x340),
+            _ => crate::symbols::parse_number(text),
+        }
+    }
+
+    #[test]
+    fn parses_ranges_with_labels_and_heights() {
+        let sheet = SpriteSheet::parse("tiles:tiles_end", resolve).unwrap();
+        assert_eq!((sheet.start, sheet.end, sheet.height, sheet.wide), (0x300, 0x340, 8, false));
+        assert_eq!(sheet.sprite_count(), 8);
+
+        let sheet = SpriteSheet::parse("0x200:0x20A:5", resolve).unwrap();
+        assert_eq!((sheet.start, sheet.end, sheet.height), (0x200, 0x20A, 5));
+        assert_eq!(sheet.sprite_count(), 2);
+        assert_eq!(sheet.describe(), "200-20A 8x5");
+    }
+
+    #[test]
+    fn rejects_bad_ranges_and_heights() {
+        for text in ["0x200", "0x200:0x210:5:1", "0x210:0x200", "0x200:0x200", "0x200:0x1001",
+                     "0x200:0x210:0", "0x200:0x210:16", "0x200:0x210:x", "missing:0x210"] {
+            assert!(SpriteSheet::parse(text, resolve).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn counts_partial_and_wide_sprites() {
+        let mut sheet = SpriteSheet::parse("0x200:0x211:8", resolve).unwrap();
+        assert_eq!(sheet.sprite_count(), 3);
+        sheet.wide = true;
+        assert_eq!(sheet.sprite_count(), 1);
+        assert_eq!((sheet.sprite_width(), sheet.sprite_height()), (16, 16));
+        assert_eq!(sheet.describe(), "200-211 16x16");
+    }
+
+    #[test]
+    fn clamps_scrolling_at_both_ends_of_memory() {
+        let mut sheet = SpriteSheet::parse("0x010:0x020", resolve).unwrap();
+        sheet.scroll(-0x100);
+        assert_eq!((sheet.start, sheet.end), (0x000, 0x010));
+        sheet.scroll(0x123);
+        assert_eq!((sheet.start, sheet.end), (0x123, 0x133));
+        sheet.scroll(0x2000);
+        assert_eq!((sheet.start, sheet.end), (0xFF0, 0x1000));
+    }
+
+    #[test]
+    fn renders_sprites_and_marks_drawn_bytes() {
+        // Two 3-row sprites, the second cut short by the end of the range
+        let mut chip = Chip8::new(&vec![0xF0, 0x90, 0xF0, 0x81, 0x00]);
+        let sheet = SpriteSheet::parse("0x200:0x205:3", resolve).unwrap();
+
+        let image = sheet.render(&chip);
+        assert_eq!((image.width, image.height), (2 * 9 + 1, 3 + 2));
+        assert_eq!(image.get_pixel(0, 0), SpriteSheet::COLOR_GAP);
+        assert_eq!(image.get_pixel(1, 1), SpriteSheet::COLOR_ON);
+        assert_eq!(image.get_pixel(5, 1), SpriteSheet::COLOR_OFF);
+        assert_eq!(image.get_pixel(2, 2), SpriteSheet::COLOR_OFF);
+        assert_eq!(image.get_pixel(4, 2), SpriteSheet::COLOR_ON);
+        // Second sprite starts one cell over: 0x81, then 0x00, then past the end
+        assert_eq!(image.get_pixel(10, 1), SpriteSheet::COLOR_ON);
+        assert_eq!(image.get_pixel(17, 1), SpriteSheet::COLOR_ON);
+        assert_eq!(image.get_pixel(10, 2), SpriteSheet::COLOR_OFF);
+        assert_eq!(image.get_pixel(10, 3), SpriteSheet::COLOR_GAP);
+
+        // Draw the first sprite's top row
+        chip.op_ANNN(0x200);
+        chip.op_DXYN(0, 0, 1);
+        let image = sheet.render(&chip);
+        assert_eq!(image.get_pixel(1, 1), SpriteSheet::COLOR_DRAWN_ON);
+        assert_eq!(image.get_pixel(5, 1), SpriteSheet::COLOR_DRAWN_OFF);
+        assert_eq!(image.get_pixel(1, 2), SpriteSheet::COLOR_ON);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+// Symbol map loaded from a text file, one symbol per line:
+//
+//     <address> <label> [<file>:<line>]
+//
+// Addresses are hex with a 0x prefix or decimal. Blank lines and lines starting
+// with '#' or ';' are ignored.
+pub struct SymbolMap {
+    labels: HashMap<String, u16>,
+    by_address: BTreeMap<u16, String>,
+    sources: HashMap<u16, (String, u32)>,
+}
+
+impl SymbolMap {
+    pub fn new() -> SymbolMap {
+        SymbolMap {
+            labels: HashMap::new(),
+            by_address: BTreeMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<SymbolMap, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading symbol file {}: {}", path, e))?;
+        SymbolMap::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolMap, String> {
+        let mut map = SymbolMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 2 || fields.len() > 3 {
+                return Err(format!("line {}: expected '<address> <label> [<file>:<line>]'", i + 1));
+            }
+
+            let address = match parse_number(fields[0]) {
+                Some(a) if a < 0x1000 => a,
+                _ => return Err(format!("line {}: invalid address '{}'", i + 1, fields[0])),
+            };
+
+            let label = fields[1];
+            if parse_number(label).is_some() || label.contains('+') {
+                return Err(format!("line {}: invalid label '{}'", i + 1, label));
+            }
+            map.labels.insert(label.to_string(), address);
+            map.by_address.entry(address).or_insert_with(|| label.to_string());
+
+            if fields.len() == 3 {
+                let source = match fields[2].rsplit_once(':') {
+                    Some((file, line)) => line.parse::<u32>().ok().map(|l| (file.to_string(), l)),
+                    None => None,
+                };
+                match source {
+                    Some(s) => { map.sources.insert(address, s); }
+                    None => return Err(format!("line {}: invalid source location '{}'", i + 1, fields[2])),
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    // Label defined exactly at address
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.by_address.get(&address).map(|l| l.as_str())
+    }
+
+    // Nearest label at or before address, with the offset from it
+    pub fn nearest_label(&self, address: u16) -> Option<(&str, u16)> {
+        self.by_address
+            .range(..=address)
+            .next_back()
+            .map(|(a, l)| (l.as_str(), address - a))
+    }
+
+    pub fn source_at(&self, address: u16) -> Option<(&str, u32)> {
+        self.sources.get(&address).map(|(f, l)| (f.as_str(), *l))
+    }
+
+    // "0x2A4 <draw_player+4>", or just "0x2A4" when no label precedes the address
+    pub fn describe(&self, address: u16) -> String {
+        match self.nearest_label(address) {
+            Some((label, 0)) => format!("{:#05X} <{}>", address, label),
+            Some((label, offset)) => format!("{:#05X} <{}+{}>", address, label, offset),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    // Label name for an address used as an operand, falling back to hex
+    pub fn operand(&self, address: u16) -> String {
+        match self.label_at(address) {
+            Some(label) => label.to_string(),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    // Accepts a number, a label or label+offset
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        if let Some(n) = parse_number(text) {
+            return Some(n);
+        }
+
+        let (label, offset) = match text.split_once('+') {
+            Some((label, offset)) => (label, parse_number(offset)?),
+            None => (text, 0),
+        };
+        self.labels.get(label).map(|a| a.wrapping_add(offset))
+    }
+}
+
+pub fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse::<u16>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "
+# comment
+; also a comment
+0x200 start main.8o:1
+0x2A0 draw_player main.8o:42
+676 draw_enemy
+";
+
+    #[test]
+    fn parses_addresses_labels_and_sources() {
+        let symbols = SymbolMap::parse(MAP).unwrap();
+        assert_eq!(symbols.label_at(0x200), Some("start"));
+        assert_eq!(symbols.label_at(0x2A4), Some("draw_enemy"));
+        assert_eq!(symbols.label_at(0x202), None);
+        assert_eq!(symbols.source_at(0x2A0), Some(("main.8o", 42)));
+        assert_eq!(symbols.source_at(0x2A4), None);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(SymbolMap::parse("0x200").is_err());
+        assert!(SymbolMap::parse("0x1000 too_far").is_err());
+        assert!(SymbolMap::parse("0x200 0x300").is_err());
+        assert!(SymbolMap::parse("0x200 a+b").is_err());
+        assert!(SymbolMap::parse("0x200 start main.8o").is_err());
+        assert!(SymbolMap::parse("0x200 start main.8o:1 extra").is_err());
+        assert_eq!(SymbolMap::parse("x\n0x200 start\nbad").err().unwrap(), "line 1: expected '<address> <label> [<file>:<line>]'");
+    }
+
+    #[test]
+    fn resolves_numbers_labels_and_offsets() {
+        let symbols = SymbolMap::parse(MAP).unwrap();
+        assert_eq!(symbols.resolve("0x2A4"), Some(0x2A4));
+        assert_eq!(symbols.resolve("0X2a4"), Some(0x2A4));
+        assert_eq!(symbols.resolve("512"), Some(0x200));
+        assert_eq!(symbols.resolve("draw_player"), Some(0x2A0));
+        assert_eq!(symbols.resolve("draw_player+2"), Some(0x2A2));
+        assert_eq!(symbols.resolve("draw_player+0x10"), Some(0x2B0));
+        assert_eq!(symbols.resolve("missing"), None);
+        assert_eq!(symbols.resolve("draw_player+x"), None);
+    }
+
+    #[test]
+    fn describes_addresses_relative_to_the_nearest_label() {
+        let symbols = SymbolMap::parse(MAP).unwrap();
+        assert_eq!(symbols.describe(0x2A0), "0x2A0 <draw_player>");
+        assert_eq!(symbols.describe(0x2A2), "0x2A2 <draw_player+2>");
+        assert_eq!(symbols.describe(0x100), "0x100");
+        assert_eq!(symbols.operand(0x2A4), "draw_enemy");
+        assert_eq!(symbols.operand(0x2A6), "0x2A6");
+    }
+
+    #[test]
+    fn keeps_the_first_label_at_an_address() {
+        let symbols = SymbolMap::parse("0x200 first\n0x200 second").unwrap();
+        assert_eq!(symbols.label_at(0x200), Some("first"));
+        assert_eq!(symbols.resolve("second"), Some(0x200));
+    }
+}
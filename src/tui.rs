@@ -0,0 +1,240 @@
+use std::io::{Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::chip8::Chip8;
+use crate::frontend::{InputEvent, InputSource, Renderer};
+use crate::palette::Palette;
+
+// Terminal frontend. Each character cell shows two pixels stacked with the
+// upper half block, foreground for the top one and background for the bottom
+// one, with registers in a panel to the right.
+pub struct Tui {
+    out: Stdout,
+    // Whether the terminal reports key releases, see TuiInput
+    release_events: bool,
+    palette: Palette,
+    title: String,
+    message: Option<String>,
+    // Beeper sounding, shown in the panel
+    sound: bool,
+}
+
+impl Tui {
+    const PANEL_COLUMN: u16 = Chip8::VIDEO_WIDTH as u16 + 2;
+
+    pub fn new() -> Tui {
+        let mut out = std::io::stdout();
+        terminal::enable_raw_mode().unwrap();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All)).unwrap();
+
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            execute!(out, event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)).unwrap();
+        }
+
+        Tui {
+            out,
+            release_events,
+            palette: Palette::named("classic").unwrap(),
+            title: String::new(),
+            message: None,
+            sound: false,
+        }
+    }
+
+    pub fn get_release_events(&self) -> bool {
+        self.release_events
+    }
+
+    fn draw_panel(&mut self, chip8: &Chip8) {
+        let registers = chip8.get_registers();
+        let mut lines: Vec<String> = (0..8)
+            .map(|i| format!("V{:X}={:02X}  V{:X}={:02X}", i, registers[i], i + 8, registers[i + 8]))
+            .collect();
+        lines.push(String::new());
+        lines.push(format!("I={:03X}   PC={:03X}", chip8.get_index_register(), chip8.get_pc()));
+        lines.push(format!("DT={:02X}    ST={:02X}", chip8.get_delay_timer(), chip8.get_sound_timer()));
+        lines.push(format!("SP={:<2}    cycle={}", chip8.get_stack().len(), chip8.get_cycles()));
+        lines.push(String::new());
+        lines.push(self.title.clone());
+        lines.push(if self.sound { "** BEEP **".to_string() } else { String::new() });
+        lines.push("Esc to quit".to_string());
+        lines.push(self.message.clone().unwrap_or_default());
+
+        for (row, line) in lines.iter().enumerate() {
+            queue!(
+                self.out,
+                cursor::MoveTo(Tui::PANEL_COLUMN, row as u16),
+                terminal::Clear(terminal::ClearType::UntilNewLine),
+                Print(line)
+            ).unwrap();
+        }
+        self.out.flush().unwrap();
+    }
+}
+
+impl Renderer for Tui {
+    fn present(&mut self, chip8: &Chip8) {
+        // Colors are only sent when they change, which keeps output small over SSH
+        let mut current = None;
+        for y in (0..Chip8::VIDEO_HEIGHT).step_by(2) {
+            queue!(self.out, cursor::MoveTo(0, (y / 2) as u16)).unwrap();
+            for x in 0..Chip8::VIDEO_WIDTH {
+                let top = self.palette.color(chip8.get_video(x, y));
+                let bottom = self.palette.color(chip8.get_video(x, y + 1));
+                if current != Some((top, bottom)) {
+                    queue!(
+                        self.out,
+                        SetForegroundColor(Color::Rgb { r: top.0, g: top.1, b: top.2 }),
+                        SetBackgroundColor(Color::Rgb { r: bottom.0, g: bottom.1, b: bottom.2 })
+                    ).unwrap();
+                    current = Some((top, bottom));
+                }
+                queue!(self.out, Print('\u{2580}')).unwrap();
+            }
+        }
+        queue!(self.out, ResetColor).unwrap();
+        self.out.flush().unwrap();
+    }
+
+    // Shown in the panel, there's no window to name
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    // Anything drawn outside the new size is gone, so start over
+    fn resize(&mut self, _width: u32, _height: u32) {
+        execute!(self.out, terminal::Clear(terminal::ClearType::All)).unwrap();
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+    }
+
+    // The panel is cheap enough to draw every loop
+    fn update(&mut self, _frame: u64, chip8: &Chip8) {
+        self.draw_panel(chip8);
+    }
+
+    fn set_sound(&mut self, on: bool) {
+        self.sound = on;
+    }
+
+    // Printing would scroll the alternate screen, so messages go in the panel
+    fn notify(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        if self.release_events {
+            let _ = execute!(self.out, event::PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Keyboard input from the terminal
+pub struct TuiInput {
+    // Whether the terminal reports key releases. Otherwise a key is released
+    // once it hasn't been reported (pressed or auto-repeated) for a while.
+    release_events: bool,
+    pressed_at: [Option<Instant>; 16],
+}
+
+impl TuiInput {
+    // A held key is reported once, then nothing until auto-repeat starts,
+    // usually after 500 to 660 ms. Waiting longer than that keeps it down.
+    const RELEASE_DELAY: Duration = Duration::from_millis(750);
+
+    pub fn new(release_events: bool) -> TuiInput {
+        TuiInput {
+            release_events,
+            pressed_at: [None; 16],
+        }
+    }
+}
+
+impl InputSource for TuiInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        while event::poll(Duration::ZERO).unwrap() {
+            let (code, modifiers, kind) = match event::read().unwrap() {
+                Event::Key(KeyEvent { code, modifiers, kind, .. }) => (code, modifiers, kind),
+                Event::Resize(width, height) => {
+                    events.push(InputEvent::Resized(width as u32, height as u32));
+                    continue;
+                }
+                _ => continue,
+            };
+            let pressed = kind != KeyEventKind::Release;
+
+            match code {
+                KeyCode::Esc => events.push(InputEvent::Quit),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => events.push(InputEvent::Quit),
+                KeyCode::Char(c) => {
+                    if let Some(key) = keypad_index(c) {
+                        events.push(InputEvent::Key(key, pressed));
+                        self.pressed_at[key as usize] = if pressed { Some(Instant::now()) } else { None };
+                    } else if kind == KeyEventKind::Press {
+                        match c {
+                            'p' => events.push(InputEvent::TogglePause),
+                            'n' => events.push(InputEvent::AdvanceFrame),
+                            'm' => events.push(InputEvent::AdvanceInstruction),
+                            '-' => events.push(InputEvent::SlowMotion),
+                            'b' => events.push(InputEvent::ToggleMute),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !self.release_events {
+            for key in 0..16 {
+                if let Some(at) = self.pressed_at[key] {
+                    if at.elapsed() > TuiInput::RELEASE_DELAY {
+                        events.push(InputEvent::Key(key as u8, false));
+                        self.pressed_at[key] = None;
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+// Same layout as the SDL frontend
+//   1 2 3 C    1 2 3 4
+//   4 5 6 D    Q W E R
+//   7 8 9 E    A S D F
+//   A 0 B F    Z X C V
+fn keypad_index(c: char) -> Option<u8> {
+    let key = match c.to_ascii_lowercase() {
+        'x' => 0x0,
+        '1' => 0x1,
+        '2' => 0x2,
+        '3' => 0x3,
+        'q' => 0x4,
+        'w' => 0x5,
+        'e' => 0x6,
+        'a' => 0x7,
+        's' => 0x8,
+        'd' => 0x9,
+        'z' => 0xA,
+        'c' => 0xB,
+        '4' => 0xC,
+        'r' => 0xD,
+        'f' => 0xE,
+        'v' => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+// Writes the beeper's samples to a 16-bit mono WAV file, one emulated frame
+// at a time. Every frame gets exactly the samples played during it, so frame
+// n starts at sample n * sample_rate / 60 and the sound lines up with a
+// recording of the same run.
+pub struct WavWriter {
+    path: String,
+    file: BufWriter<File>,
+    samples: u64,
+    frames: u64,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+
+    pub fn start(path: &str, sample_rate: u32) -> Result<WavWriter, String> {
+        let file = match File::create(path) {
+            Ok(f) => BufWriter::new(f),
+            Err(e) => return Err(format!("Error creating {}: {}", path, e)),
+        };
+        let mut wav = WavWriter {
+            path: path.to_string(),
+            file,
+            samples: 0,
+            frames: 0,
+        };
+        // Sizes are filled in by finish
+        wav.write_header(sample_rate, 0).map_err(|e| format!("Error writing {}: {}", path, e))?;
+        Ok(wav)
+    }
+
+    // Appends the samples of the next frame, from -1 to 1
+    pub fn add_frame(&mut self, samples: &[f32]) -> Result<(), String> {
+        let data: Vec<u8> = samples.iter()
+            .flat_map(|s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16).to_le_bytes())
+            .collect();
+        self.samples += samples.len() as u64;
+        self.frames += 1;
+        self.file.write_all(&data).map_err(|e| format!("Error writing {}: {}", self.path, e))
+    }
+
+    // Completes the header and returns the path and the number of frames written
+    pub fn finish(mut self) -> Result<(String, u64), String> {
+        let data_size = (self.samples * 2).min((u32::MAX - WavWriter::HEADER_SIZE) as u64) as u32;
+        let result = self.file.seek(SeekFrom::Start(4))
+            .and_then(|_| self.file.write_all(&(data_size + WavWriter::HEADER_SIZE - 8).to_le_bytes()))
+            .and_then(|_| self.file.seek(SeekFrom::Start(40)))
+            .and_then(|_| self.file.write_all(&data_size.to_le_bytes()))
+            .and_then(|_| self.file.flush());
+        match result {
+            Ok(()) => Ok((self.path.clone(), self.frames)),
+            Err(e) => Err(format!("Error writing {}: {}", self.path, e)),
+        }
+    }
+
+    fn write_header(&mut self, sample_rate: u32, data_size: u32) -> std::io::Result<()> {
+        let mut header = Vec::with_capacity(WavWriter::HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(data_size + WavWriter::HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        // Bytes per second and per sample
+        header.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        self.file.write_all(&header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_header_sizes_and_samples() {
+        let path = std::env::temp_dir().join(format!("wav_test_{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut wav = WavWriter::start(path, 48000).unwrap();
+        wav.add_frame(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        wav.add_frame(&[2.0, -2.0]).unwrap();
+        wav.add_frame(&[]).unwrap();
+        assert_eq!(wav.finish().unwrap(), (path.to_string(), 3));
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(bytes.len(), 44 + 6 * 2);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        // PCM, mono, 48 kHz, 96000 bytes per second, 2 bytes per sample, 16 bits
+        assert_eq!((u16_at(&bytes, 20), u16_at(&bytes, 22)), (1, 1));
+        assert_eq!((u32_at(&bytes, 24), u32_at(&bytes, 28)), (48000, 96000));
+        assert_eq!((u16_at(&bytes, 32), u16_at(&bytes, 34)), (2, 16));
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 6 * 2);
+
+        let samples: Vec<i16> = bytes[44..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        // Out of range samples are clamped
+        assert_eq!(samples, vec![0, i16::MAX, -i16::MAX, 16384, i16::MAX, -i16::MAX]);
+    }
+
+    #[test]
+    fn reports_files_it_cannot_create() {
+        let error = WavWriter::start("/nonexistent/directory/out.wav", 48000).err().unwrap();
+        assert!(error.starts_with("Error creating /nonexistent/directory/out.wav"));
+    }
+}
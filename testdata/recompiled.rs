@@ -0,0 +1,83 @@
+// Generated by `chip-8-emu-rust recompile` from lockstep.ch8. Do not edit.
+//
+// Add this file as a module of the emulator and call `run` in place of
+// Chip8::cycle. Each basic block is a function over the Chip8 API; any
+// other address is interpreted.
+
+use crate::chip8::Chip8;
+
+pub const ROM: [u8; 18] = [
+    0x60, 0x00, 0x62, 0x0F, 0xA2, 0x00, 0xF2, 0x1E, 0x70, 0x01, 0xF0, 0x55,
+    0x61, 0x00, 0x71, 0x05, 0x12, 0x08,
+];
+
+pub fn new_chip() -> Chip8 {
+    Chip8::new(&ROM.to_vec())
+}
+
+// Runs at least budget instructions, finishing the block in progress.
+// Returns the instructions executed.
+pub fn run(chip: &mut Chip8, budget: u64) -> u64 {
+    let mut executed = 0;
+    while executed < budget {
+        executed += dispatch(chip);
+    }
+    executed
+}
+
+// Maps the PC to its block, which also resolves BNNN jumps at run time
+fn dispatch(chip: &mut Chip8) -> u64 {
+    match chip.get_pc() {
+        0x200 if intact(chip, 0x200, 0x208) => block_200(chip),
+        0x208 if intact(chip, 0x208, 0x20C) => block_208(chip),
+        0x20C if intact(chip, 0x20C, 0x212) => block_20c(chip),
+        _ => {
+            chip.cycle();
+            1
+        }
+    }
+}
+
+// Whether memory in [start, end) still holds the ROM's code
+fn intact(chip: &Chip8, start: u16, end: u16) -> bool {
+    (start..end).all(|a| chip.get_memory(a) == ROM[(a - 0x200) as usize])
+}
+
+// 0x200
+fn block_200(chip: &mut Chip8) -> u64 {
+    // 200  LD V0, 0x00
+    chip.op_6XNN(0x0, 0x00);
+    // 202  LD V2, 0x0F
+    chip.op_6XNN(0x2, 0x0F);
+    // 204  LD I, 0x200
+    chip.op_ANNN(0x200);
+    // 206  ADD I, V2
+    chip.op_FX1E(0x2);
+    chip.set_pc(0x208);
+    chip.retire(4);
+    4
+}
+
+// 0x208
+fn block_208(chip: &mut Chip8) -> u64 {
+    // 208  ADD V0, 0x01
+    chip.op_7XNN(0x0, 0x01);
+    // 20A  LD [I], V0
+    chip.op_FX55(0x0);
+    chip.set_pc(0x20C);
+    chip.retire(2);
+    2
+}
+
+// 0x20C
+fn block_20c(chip: &mut Chip8) -> u64 {
+    // 20C  LD V1, 0x00
+    chip.op_6XNN(0x1, 0x00);
+    // 20E  ADD V1, 0x05
+    chip.op_7XNN(0x1, 0x05);
+    // 210  JP 0x208
+    chip.set_pc(0x212);
+    chip.op_1NNN(0x208);
+    chip.retire(3);
+    3
+}